@@ -1,45 +1,318 @@
+use anyhow::Result;
+use jljoin::JoinOptions;
+use jljoin::Joiner;
+use jljoin::MergeStrategy;
+use jljoin::OnConflict;
+use jljoin::OnError;
+use jljoin::Outer;
+use jljoin::OutputShape;
+use serde_json::json;
 use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
 use std::process;
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug)]
 pub(super) struct Options {
     pub file1: String,
     pub file2: String,
-    pub key1: String,
-    pub key2: String,
-    pub allow_no_key: bool,
-    pub merge: Option<u8>,
+    pub joiner: Joiner,
+    pub stats: Option<Option<String>>,
+}
+
+// Error of the command line arguments
+#[derive(Debug)]
+pub(super) struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for UsageError {}
+
+macro_rules! usage_error {
+    ($($arg:tt)*) => {
+        anyhow::Error::new(UsageError(format!($($arg)*)))
+    };
+}
+
+fn opt_display_name(name: &str) -> String {
+    if name.len() == 1 {
+        format!("-{}", name)
+    } else {
+        format!("--{}", name)
+    }
+}
+
+fn parse_filenum(matches: &getopts::Matches, name: &str) -> Result<Option<u8>> {
+    match matches.opt_str(name) {
+        Some(n) => match &*n {
+            "1" => Ok(Some(1)),
+            "2" => Ok(Some(2)),
+            _ => Err(usage_error!(
+                "Specify 1 or 2 for '{}'",
+                opt_display_name(name)
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+fn opt_key_list(matches: &getopts::Matches, name: &str) -> Option<String> {
+    let keys = matches.opt_strs(name);
+
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys.join(","))
+    }
+}
+
+// Parse a size such as "1024", "64K", "64M" or "1G"
+fn parse_size(s: &str) -> Option<usize> {
+    let (num, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+
+    let n: usize = num.parse().ok()?;
+
+    match unit {
+        'B' => Some(n),
+        'K' => n.checked_mul(1024),
+        'M' => n.checked_mul(1024 * 1024),
+        'G' => n.checked_mul(1024 * 1024 * 1024),
+        _ => None,
+    }
+}
+
+fn opt_size(matches: &getopts::Matches, name: &str) -> Result<Option<usize>> {
+    match matches.opt_str(name) {
+        Some(s) => match parse_size(&s) {
+            Some(n) => Ok(Some(n)),
+            None => Err(usage_error!(
+                "Invalid size for '{}': {}",
+                opt_display_name(name),
+                s
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+// Parse "NAME1,NAME2"
+fn opt_name_pair(matches: &getopts::Matches, name: &str) -> Result<Option<(String, String)>> {
+    let s = match matches.opt_str(name) {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+
+    match s.split(',').collect::<Vec<_>>()[..] {
+        [name1, name2] if !name1.is_empty() && !name2.is_empty() => {
+            Ok(Some((name1.to_string(), name2.to_string())))
+        }
+        _ => Err(usage_error!(
+            "Specify NAME1,NAME2 for '{}'",
+            opt_display_name(name)
+        )),
+    }
+}
+
+// Number of the smaller file to load into memory
+fn smaller_file(path1: &str, path2: &str) -> u8 {
+    let len = |path: &str| {
+        if path == "-" {
+            None
+        } else {
+            fs::metadata(path).ok().map(|m| m.len())
+        }
+    };
+
+    match (len(path1), len(path2)) {
+        (Some(len1), Some(len2)) if len1 < len2 => 1,
+        (Some(_), None) => 1,
+        _ => 2,
+    }
 }
 
 fn print_usage(program: &str, opts: getopts::Options) {
-    let brief = format!("Usage: {} [OPTIONS] FILE1 FILE2", program);
+    let brief = format!(
+        "Usage: {} [OPTIONS] FILE1 FILE2\n\nWith FILE1 or FILE2 of '-', read standard input.",
+        program
+    );
     print!("{}", opts.usage(&brief));
 }
 
-pub(super) fn parse_opts() -> Options {
+pub(super) fn parse_opts() -> Result<Options> {
     let args: Vec<String> = env::args().collect();
     let program = &args[0];
     let mut opts = getopts::Options::new();
 
-    opts.optopt("k", "key", "JSON key to join", "KEY");
-    opts.optopt("1", "key1", "JSON key to join of FILE1", "KEY1");
-    opts.optopt("2", "key2", "JSON key to join of FILE2", "KEY2");
+    opts.optmulti(
+        "k",
+        "key",
+        "JSON key to join (dotted path or JSON Pointer, repeatable or comma-separated)",
+        "KEY",
+    );
+    opts.optmulti("1", "key1", "JSON key to join of FILE1", "KEY1");
+    opts.optmulti("2", "key2", "JSON key to join of FILE2", "KEY2");
     opts.optopt(
         "m",
         "merge",
         "Merge the paired JSON",
         "PRIORITY_FILENUM (1 or 2)",
     );
+    opts.optopt(
+        "",
+        "merge-strategy",
+        "How to merge the paired JSON (default: deep), optionally with ignore-nulls (e.g. deep,ignore-nulls)",
+        "STRATEGY (shallow, deep, array-concat or array-union)",
+    );
+    opts.optmulti(
+        "",
+        "prefer",
+        "Prefer the members at the paths of the file when merging (repeatable, e.g. 2:price,stock)",
+        "FILENUM:PATHS",
+    );
+    opts.optopt(
+        "",
+        "prefix1",
+        "Prefix of the members of FILE1 when merging (except the join key)",
+        "PREFIX",
+    );
+    opts.optopt(
+        "",
+        "prefix2",
+        "Prefix of the members of FILE2 when merging (except the join key)",
+        "PREFIX",
+    );
+    opts.optopt(
+        "",
+        "nest",
+        "Nest the members of FILE1 and FILE2 under the names when merging (except the join key)",
+        "NAME1,NAME2",
+    );
+    opts.optopt(
+        "",
+        "merge-conflict",
+        "How to handle the members with different values when merging (default: resolve)",
+        "MODE (resolve or error)",
+    );
+    opts.optopt(
+        "",
+        "conflicts",
+        "Write the members with different values when merging to FILE as NDJSON",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "outer",
+        "Output unpaired lines as well",
+        "TYPE (left, right or full)",
+    );
+    opts.optopt(
+        "",
+        "placeholder",
+        "Placeholder for the missing side of unpaired lines",
+        "VALUE (null or {})",
+    );
+    opts.optopt(
+        "",
+        "output-shape",
+        "Shape of the paired lines (default: array)",
+        "SHAPE (array or object)",
+    );
+    opts.optopt(
+        "",
+        "names",
+        "Names of the members of FILE1 and FILE2 in the object shape (default: left,right)",
+        "NAME1,NAME2",
+    );
+    opts.optopt(
+        "",
+        "group",
+        "Output each line of the other file once with the paired lines of the file in the array member NAME",
+        "FILENUM:NAME",
+    );
+    opts.optopt(
+        "",
+        "only-unmatched",
+        "Output only the unpaired lines of the file (anti-join)",
+        "FILENUM (1 or 2)",
+    );
+    opts.optopt(
+        "",
+        "semi",
+        "Output each paired line of the file once (semi-join)",
+        "FILENUM (1 or 2)",
+    );
+    opts.optflag(
+        "",
+        "check-order",
+        "Check that the input is correctly sorted (default)",
+    );
+    opts.optflag(
+        "",
+        "nocheck-order",
+        "Do not check that the input is correctly sorted",
+    );
+    opts.optopt(
+        "",
+        "block-buffer-size",
+        "Memory size to buffer the lines of FILE2 with the same key (default: 64M)",
+        "SIZE",
+    );
+    opts.optflag(
+        "",
+        "hash",
+        "Load the smaller file into memory and join unsorted inputs",
+    );
+    opts.optopt(
+        "",
+        "hash-file",
+        "Load the file into memory instead of the smaller one (implies '--hash')",
+        "FILENUM (1 or 2)",
+    );
+    opts.optflag("", "sort", "Sort the files by the join key before joining");
+    opts.optopt(
+        "",
+        "memory-limit",
+        "Memory size to sort the lines of each file in (default: 256M)",
+        "SIZE",
+    );
+    opts.optopt(
+        "",
+        "on-error",
+        "How to handle malformed lines (default: fail)",
+        "MODE (fail, skip or quarantine=PATH)",
+    );
+    opts.optopt(
+        "",
+        "max-errors",
+        "Fail if there are more malformed lines than N",
+        "N",
+    );
+    opts.optflag("", "stats", "Print the join statistics to stderr");
+    opts.optopt(
+        "",
+        "stats-file",
+        "Write the join statistics to FILE as JSON",
+        "FILE",
+    );
     opts.optflag("", "allow-no-key", "Allow no key");
     opts.optflag("v", "version", "Print version and exit");
     opts.optflag("h", "help", "Print usage and exit");
 
-    let matches = opts.parse(&args[1..]).unwrap();
+    let matches = opts.parse(&args[1..]).map_err(|e| usage_error!("{}", e))?;
 
     if args.len() == 1 || matches.opt_present("h") {
-        print_usage(&program, opts);
+        print_usage(program, opts);
         process::exit(0)
     }
 
@@ -48,46 +321,239 @@ pub(super) fn parse_opts() -> Options {
         process::exit(0)
     }
 
-    let opt_key = matches.opt_str("k");
-    let opt_key1 = matches.opt_str("1");
-    let opt_key2 = matches.opt_str("2");
+    let opt_key = opt_key_list(&matches, "k");
+    let opt_key1 = opt_key_list(&matches, "1");
+    let opt_key2 = opt_key_list(&matches, "2");
+
+    let (key1, key2) = match (opt_key, opt_key1, opt_key2) {
+        (Some(key), _, _) => (key.clone(), key),
+        (None, Some(key1), Some(key2)) => (key1, key2),
+        _ => return Err(usage_error!("'-k' or '-1/-2' is required")),
+    };
+
+    let (file1, file2) = match matches.free.len() {
+        2 => (matches.free[0].to_string(), matches.free[1].to_string()),
+        _ => return Err(usage_error!("FILE1 and FILE2 are required")),
+    };
 
-    if opt_key.is_none() && (opt_key1.is_none() || opt_key2.is_none()) {
-        panic!("'-k' or '-1/-2' is required");
+    if file1 == "-" && file2 == "-" {
+        return Err(usage_error!("FILE1 and FILE2 cannot both be '-'"));
     }
 
-    let (key1, key2) = if opt_key.is_some() {
-        let key = opt_key.unwrap();
-        (key.clone(), key)
-    } else {
-        (opt_key1.unwrap(), opt_key2.unwrap())
+    let mut join_opts = JoinOptions::new()
+        .allow_no_key(matches.opt_present("allow-no-key"))
+        .file_names(&file1, &file2);
+
+    if let Some(n) = parse_filenum(&matches, "m")? {
+        join_opts = join_opts.merge(n);
+    }
+
+    if let Some(s) = matches.opt_str("merge-strategy") {
+        let mut merge_strategy = None;
+        let mut ignore_nulls = false;
+
+        for name in s.split(',') {
+            let strategy = match name {
+                "shallow" => MergeStrategy::Shallow,
+                "deep" => MergeStrategy::Deep,
+                "array-concat" => MergeStrategy::ArrayConcat,
+                "array-union" => MergeStrategy::ArrayUnion,
+                "ignore-nulls" if !ignore_nulls => {
+                    ignore_nulls = true;
+                    continue;
+                }
+                _ => {
+                    return Err(usage_error!(
+                        "Specify shallow, deep, array-concat or array-union (and/or ignore-nulls) for '--merge-strategy'"
+                    ))
+                }
+            };
+
+            if merge_strategy.replace(strategy).is_some() {
+                return Err(usage_error!(
+                    "Specify only one strategy for '--merge-strategy'"
+                ));
+            }
+        }
+
+        if let Some(strategy) = merge_strategy {
+            join_opts = join_opts.merge_strategy(strategy);
+        }
+
+        join_opts = join_opts.ignore_nulls(ignore_nulls);
+    }
+
+    for rule in matches.opt_strs("prefer") {
+        let filenum = match rule.split_once(':') {
+            Some(("1", paths)) if !paths.is_empty() => 1,
+            Some(("2", paths)) if !paths.is_empty() => 2,
+            _ => return Err(usage_error!("Specify FILENUM:PATHS for '--prefer'")),
+        };
+
+        join_opts = join_opts.prefer(filenum, &rule[2..]);
+    }
+
+    join_opts = join_opts.prefixes(
+        &matches.opt_str("prefix1").unwrap_or_default(),
+        &matches.opt_str("prefix2").unwrap_or_default(),
+    );
+
+    if let Some((name1, name2)) = opt_name_pair(&matches, "nest")? {
+        join_opts = join_opts.nest(&name1, &name2);
+    }
+
+    let on_conflict = match (
+        matches.opt_str("merge-conflict"),
+        matches.opt_str("conflicts"),
+    ) {
+        (None, None) => OnConflict::Resolve,
+        (Some(mode), None) if mode == "resolve" => OnConflict::Resolve,
+        (Some(mode), None) if mode == "error" => OnConflict::Fail,
+        (Some(_), None) => {
+            return Err(usage_error!(
+                "Specify resolve or error for '--merge-conflict'"
+            ))
+        }
+        (Some(mode), Some(path)) if mode == "resolve" => OnConflict::Report(PathBuf::from(path)),
+        (None, Some(path)) => OnConflict::Report(PathBuf::from(path)),
+        (Some(_), Some(_)) => {
+            return Err(usage_error!(
+                "'--conflicts' cannot be used with '--merge-conflict error'"
+            ))
+        }
     };
 
-    let merge = match matches.opt_str("m") {
-        Some(n) => match &*n {
-            "1" => Some(1),
-            "2" => Some(2),
-            _ => panic!("Specify 1 or 2 for '-m'"),
+    join_opts = join_opts.on_conflict(on_conflict);
+
+    if let Some(t) = matches.opt_str("outer") {
+        let outer = match &*t {
+            "left" => Outer::Left,
+            "right" => Outer::Right,
+            "full" => Outer::Full,
+            _ => return Err(usage_error!("Specify left, right or full for '--outer'")),
+        };
+
+        join_opts = join_opts.outer(outer);
+    }
+
+    if let Some(v) = matches.opt_str("placeholder") {
+        let placeholder = match &*v {
+            "null" => json!(null),
+            "{}" => json!({}),
+            _ => return Err(usage_error!("Specify null or {{}} for '--placeholder'")),
+        };
+
+        join_opts = join_opts.placeholder(placeholder);
+    }
+
+    if let Some(s) = matches.opt_str("output-shape") {
+        let output_shape = match &*s {
+            "array" => OutputShape::Array,
+            "object" => OutputShape::Object,
+            _ => return Err(usage_error!("Specify array or object for '--output-shape'")),
+        };
+
+        join_opts = join_opts.output_shape(output_shape);
+    }
+
+    if let Some((name1, name2)) = opt_name_pair(&matches, "names")? {
+        join_opts = join_opts.names(&name1, &name2);
+    }
+
+    if let Some(n) = parse_filenum(&matches, "only-unmatched")? {
+        join_opts = join_opts.only_unmatched(n);
+    }
+
+    if let Some(n) = parse_filenum(&matches, "semi")? {
+        join_opts = join_opts.semi(n);
+    }
+
+    if matches.opt_present("check-order") && matches.opt_present("nocheck-order") {
+        return Err(usage_error!(
+            "'--check-order' and '--nocheck-order' cannot be used together"
+        ));
+    }
+
+    join_opts = join_opts.check_order(!matches.opt_present("nocheck-order"));
+
+    let group = match matches.opt_str("group") {
+        Some(s) => match s.split_once(':') {
+            Some(("1", name)) if !name.is_empty() => Some((1, name.to_string())),
+            Some(("2", name)) if !name.is_empty() => Some((2, name.to_string())),
+            _ => return Err(usage_error!("Specify FILENUM:NAME for '--group'")),
         },
         None => None,
     };
 
-    let allow_no_key = matches.opt_present("allow-no-key");
+    if let Some((n, ref name)) = group {
+        join_opts = join_opts.group(n, name);
+    }
 
-    let (file1, file2) = match matches.free.len() {
-        2 => (matches.free[0].to_string(), matches.free[1].to_string()),
-        _ => {
-            print_usage(&program, opts);
-            process::exit(1)
-        }
+    if let Some(size) = opt_size(&matches, "block-buffer-size")? {
+        join_opts = join_opts.block_buffer_size(size);
+    }
+
+    // With `--group`, the grouped file is loaded into memory
+    let hash = match (parse_filenum(&matches, "hash-file")?, &group) {
+        (Some(n), _) => Some(n),
+        (None, _) if !matches.opt_present("hash") => None,
+        (None, Some((n, _))) => Some(*n),
+        (None, None) => Some(smaller_file(&file1, &file2)),
     };
 
-    Options {
-        file1: file1,
-        file2: file2,
-        key1: key1,
-        key2: key2,
-        allow_no_key: allow_no_key,
-        merge: merge,
+    if let Some(n) = hash {
+        join_opts = join_opts.hash(n);
     }
+
+    join_opts = join_opts.sort(matches.opt_present("sort"));
+
+    if let Some(size) = opt_size(&matches, "memory-limit")? {
+        join_opts = join_opts.memory_limit(size);
+    }
+
+    if let Some(mode) = matches.opt_str("on-error") {
+        let on_error = match &*mode {
+            "fail" => OnError::Fail,
+            "skip" => OnError::Skip,
+            _ if mode.starts_with("quarantine=") && mode.len() > "quarantine=".len() => {
+                OnError::Quarantine(PathBuf::from(&mode["quarantine=".len()..]))
+            }
+            _ => {
+                return Err(usage_error!(
+                    "Specify fail, skip or quarantine=PATH for '--on-error'"
+                ))
+            }
+        };
+
+        join_opts = join_opts.on_error(on_error);
+    }
+
+    if let Some(n) = matches.opt_str("max-errors") {
+        let n = n
+            .parse::<u64>()
+            .map_err(|_| usage_error!("Invalid number for '--max-errors': {}", n))?;
+        join_opts = join_opts.max_errors(n);
+    }
+
+    let stats = match (matches.opt_present("stats"), matches.opt_str("stats-file")) {
+        (false, None) => None,
+        (true, None) => Some(None),
+        (false, Some(path)) => Some(Some(path)),
+        (true, Some(_)) => {
+            return Err(usage_error!(
+                "'--stats' and '--stats-file' cannot be used together"
+            ))
+        }
+    };
+
+    // The options that cannot be used together are usage errors
+    let joiner = Joiner::with_keys(&key1, &key2).options(join_opts);
+    joiner.check().map_err(|e| usage_error!("{}", e))?;
+
+    Ok(Options {
+        file1,
+        file2,
+        joiner,
+        stats,
+    })
 }
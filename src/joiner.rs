@@ -0,0 +1,303 @@
+use crate::ndjson;
+use crate::ndjson::MergeStrategy;
+use crate::ndjson::OnConflict;
+use crate::ndjson::OnError;
+use crate::ndjson::Opts;
+use crate::ndjson::Outer;
+use crate::ndjson::OutputShape;
+use crate::ndjson::RecordSink;
+use crate::ndjson::RecordSource;
+use crate::ndjson::Rows;
+use crate::ndjson::Stats;
+use anyhow::Result;
+use serde_json::Value;
+use std::io;
+
+/// Options of the join.
+///
+/// The defaults behave like `jljoin -k KEY FILE1 FILE2`: inner join, pair output
+/// (`[json1,json2]`) and inputs that must be sorted by the key.
+///
+/// The options are checked by [`JoinOptions::check`] before joining.
+#[derive(Debug, Clone, Default)]
+pub struct JoinOptions(Opts);
+
+impl JoinOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn opts(&self) -> &Opts {
+        &self.0
+    }
+
+    /// Check that the file numbers are 1 or 2 and that the options can be used together
+    /// (e.g. the semi-join cannot be used with merging).
+    pub fn check(&self) -> Result<()> {
+        ndjson::check_opts(&self.0)
+    }
+
+    /// Join the lines without the key as if the key were `null`.
+    pub fn allow_no_key(mut self, allow: bool) -> Self {
+        self.0.allow_no_key = allow;
+        self
+    }
+
+    /// Merge the paired JSON, preferring the members of the file (1 or 2).
+    pub fn merge(mut self, filenum: u8) -> Self {
+        self.0.merge = Some(filenum);
+        self
+    }
+
+    /// How to merge the paired JSON (default: [`MergeStrategy::Deep`]).
+    pub fn merge_strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.0.merge_strategy = strategy;
+        self
+    }
+
+    /// Do not let `null` overwrite the value of the other file when merging.
+    pub fn ignore_nulls(mut self, ignore: bool) -> Self {
+        self.0.ignore_nulls = ignore;
+        self
+    }
+
+    /// Prefer the members at the paths of the file (1 or 2) when merging, instead of the file of [`JoinOptions::merge`].
+    ///
+    /// The paths are comma-separated dotted paths or JSON Pointers in the merged JSON.
+    /// The rule applies to the members under the path unless a longer path has its own rule.
+    pub fn prefer(mut self, filenum: u8, paths: &str) -> Self {
+        self.0.prefer.push((filenum, paths.to_string()));
+        self
+    }
+
+    /// Prefixes of the names of the members of FILE1 and FILE2 when merging.
+    /// The members of the join key are not prefixed.
+    pub fn prefixes(mut self, prefix1: &str, prefix2: &str) -> Self {
+        self.0.prefixes = (prefix1.to_string(), prefix2.to_string());
+        self
+    }
+
+    /// Nest the members of FILE1 and FILE2 under the names when merging.
+    /// The members of the join key are not nested.
+    pub fn nest(mut self, name1: &str, name2: &str) -> Self {
+        self.0.nest = Some((name1.to_string(), name2.to_string()));
+        self
+    }
+
+    /// How to handle the members of the paired JSON with different values when merging
+    /// (default: [`OnConflict::Resolve`]).
+    pub fn on_conflict(mut self, on_conflict: OnConflict) -> Self {
+        self.0.on_conflict = on_conflict;
+        self
+    }
+
+    /// Output the unpaired lines as well.
+    pub fn outer(mut self, outer: Outer) -> Self {
+        self.0.outer = Some(outer);
+        self
+    }
+
+    /// Placeholder for the missing side of the unpaired lines (default: `null`).
+    pub fn placeholder(mut self, placeholder: Value) -> Self {
+        self.0.placeholder = placeholder;
+        self
+    }
+
+    /// Shape of the output of the paired lines (default: [`OutputShape::Array`]).
+    /// It is ignored when merging the paired JSON.
+    pub fn output_shape(mut self, shape: OutputShape) -> Self {
+        self.0.output_shape = shape;
+        self
+    }
+
+    /// Names of the members of FILE1 and FILE2 in [`OutputShape::Object`] (default: `left` and `right`).
+    pub fn names(mut self, name1: &str, name2: &str) -> Self {
+        self.0.names = (name1.to_string(), name2.to_string());
+        self
+    }
+
+    /// Output each record of the other file once with the matched records of the file (1 or 2)
+    /// collected into the array member `name`, instead of a line for each pair.
+    ///
+    /// [`Outer`] may include only the other file (its unpaired records have an empty array).
+    /// With [`JoinOptions::hash`], the file must be loaded into memory.
+    ///
+    /// Grouping is done by the output of [`Joiner::join`] and [`NdjsonSink`](crate::NdjsonSink).
+    /// The rows of [`Joiner::rows`] and [`Joiner::record_rows`] and the rows written to other sinks
+    /// are not grouped.
+    pub fn group(mut self, filenum: u8, name: &str) -> Self {
+        self.0.group = Some((filenum, name.to_string()));
+        self
+    }
+
+    /// Output only the unpaired lines of the file (1 or 2).
+    pub fn only_unmatched(mut self, filenum: u8) -> Self {
+        self.0.only_unmatched = Some(filenum);
+        self
+    }
+
+    /// Output each paired line of the file (1 or 2) once.
+    pub fn semi(mut self, filenum: u8) -> Self {
+        self.0.semi = Some(filenum);
+        self
+    }
+
+    /// Check that the inputs are sorted by the key (default: true).
+    pub fn check_order(mut self, check: bool) -> Self {
+        self.0.check_order = check;
+        self
+    }
+
+    /// Names of the inputs in error messages (default: `FILE1` and `FILE2`).
+    pub fn file_names(mut self, name1: &str, name2: &str) -> Self {
+        self.0.file_names = (name1.to_string(), name2.to_string());
+        self
+    }
+
+    /// Memory size to buffer the lines of FILE2 with the same key (default: 64 MiB).
+    pub fn block_buffer_size(mut self, size: usize) -> Self {
+        self.0.block_buffer_size = size;
+        self
+    }
+
+    /// Load the file (1 or 2) into memory and join unsorted inputs.
+    pub fn hash(mut self, filenum: u8) -> Self {
+        self.0.hash = Some(filenum);
+        self
+    }
+
+    /// Sort the inputs by the key before joining.
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.0.sort = sort;
+        self
+    }
+
+    /// Memory size to sort the lines of each input in (default: 256 MiB).
+    pub fn memory_limit(mut self, size: usize) -> Self {
+        self.0.memory_limit = size;
+        self
+    }
+
+    /// How to handle malformed lines (default: [`OnError::Fail`]).
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.0.on_error = on_error;
+        self
+    }
+
+    /// Fail if there are more malformed lines than `n`.
+    pub fn max_errors(mut self, n: u64) -> Self {
+        self.0.max_errors = Some(n);
+        self
+    }
+}
+
+/// Joiner of two NDJSON inputs.
+///
+/// The keys are dotted paths (`user.id`) or JSON Pointers (`/user/id`),
+/// comma-separated for a composite key.
+#[derive(Debug, Clone)]
+pub struct Joiner {
+    key1: String,
+    key2: String,
+    options: JoinOptions,
+}
+
+impl Joiner {
+    /// Join by the same key in both inputs.
+    pub fn new(key: &str) -> Self {
+        Self::with_keys(key, key)
+    }
+
+    /// Join by `key1` of FILE1 and `key2` of FILE2.
+    pub fn with_keys(key1: &str, key2: &str) -> Self {
+        Joiner {
+            key1: key1.to_string(),
+            key2: key2.to_string(),
+            options: JoinOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: JoinOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Check that the keys are valid and have the same number of paths,
+    /// and the options by [`JoinOptions::check`].
+    pub fn check(&self) -> Result<()> {
+        ndjson::check_keys(&self.key1, &self.key2)?;
+        self.options.check()
+    }
+
+    /// Join the inputs and write the result to `out` as NDJSON.
+    ///
+    /// The inputs are read once from the start, so they need not be seekable.
+    pub fn join<R1, R2, W>(&self, file1: R1, file2: R2, out: W) -> Result<Stats>
+    where
+        R1: io::Read,
+        R2: io::Read,
+        W: io::Write,
+    {
+        ndjson::join(
+            file1,
+            &self.key1,
+            file2,
+            &self.key2,
+            out,
+            self.options.0.clone(),
+        )
+    }
+
+    /// Join the inputs lazily and iterate over the rows.
+    ///
+    /// The rows are the same as the lines written by [`Joiner::join`], except that
+    /// they are not formatted (`--merge`, `--group`, the output shape and the placeholder are not applied).
+    pub fn rows<'a, R1, R2>(&self, file1: R1, file2: R2) -> Result<Rows<'a>>
+    where
+        R1: io::Read + 'a,
+        R2: io::Read + 'a,
+    {
+        ndjson::rows(file1, &self.key1, file2, &self.key2, self.options.0.clone())
+    }
+
+    /// Join the records of the sources and write the rows to the sink.
+    ///
+    /// The rows are written one by one for each pair; only [`NdjsonSink`](crate::NdjsonSink)
+    /// formats them by the options it was created with (e.g. [`JoinOptions::group`]).
+    pub fn join_records<'a, S1, S2, K>(
+        &self,
+        source1: S1,
+        source2: S2,
+        sink: &mut K,
+    ) -> Result<Stats>
+    where
+        S1: RecordSource + 'a,
+        S2: RecordSource + 'a,
+        K: RecordSink,
+    {
+        ndjson::join_into(
+            Box::new(source1),
+            &self.key1,
+            Box::new(source2),
+            &self.key2,
+            sink,
+            self.options.0.clone(),
+        )
+    }
+
+    /// Join the records of the sources lazily and iterate over the rows.
+    pub fn record_rows<'a, S1, S2>(&self, source1: S1, source2: S2) -> Result<Rows<'a>>
+    where
+        S1: RecordSource + 'a,
+        S2: RecordSource + 'a,
+    {
+        ndjson::rows_from(
+            Box::new(source1),
+            &self.key1,
+            Box::new(source2),
+            &self.key2,
+            self.options.0.clone(),
+            true,
+        )
+    }
+}
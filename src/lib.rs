@@ -0,0 +1,44 @@
+//! Join two NDJSON streams by a JSON key, like `join(1)`.
+//!
+//! ```
+//! use jljoin::Joiner;
+//! use jljoin::JoinOptions;
+//! use jljoin::Outer;
+//!
+//! let orders = "{\"id\":1,\"item\":\"apple\"}\n{\"id\":3,\"item\":\"lemon\"}\n";
+//! let users = "{\"id\":1,\"name\":\"alice\"}\n{\"id\":2,\"name\":\"bob\"}\n";
+//! let mut out = Vec::new();
+//!
+//! let stats = Joiner::new("id")
+//!     .options(JoinOptions::new().merge(1).outer(Outer::Left))
+//!     .join(orders.as_bytes(), users.as_bytes(), &mut out)
+//!     .unwrap();
+//!
+//! assert_eq!(
+//!     String::from_utf8(out).unwrap(),
+//!     "{\"id\":1,\"item\":\"apple\",\"name\":\"alice\"}\n{\"id\":3,\"item\":\"lemon\"}\n"
+//! );
+//! assert_eq!(stats.matched_keys, 1);
+//! ```
+
+mod joiner;
+mod ndjson;
+
+pub use joiner::JoinOptions;
+pub use joiner::Joiner;
+pub use ndjson::FileStats;
+pub use ndjson::JoinedRow;
+pub use ndjson::MalformedRecord;
+pub use ndjson::MergeStrategy;
+pub use ndjson::NdjsonSink;
+pub use ndjson::NdjsonSource;
+pub use ndjson::OnConflict;
+pub use ndjson::OnError;
+pub use ndjson::Outer;
+pub use ndjson::OutputShape;
+pub use ndjson::Position;
+pub use ndjson::Record;
+pub use ndjson::RecordSink;
+pub use ndjson::RecordSource;
+pub use ndjson::Rows;
+pub use ndjson::Stats;
@@ -1,25 +1,65 @@
 mod cli;
-mod ndjson;
 
+use anyhow::Context;
+use anyhow::Result;
 use std::fs;
 use std::io;
+use std::process;
+
+// Exit codes (cf. sysexits.h)
+const EXIT_USAGE: i32 = 64;
+const EXIT_DATA: i32 = 65;
+const EXIT_IO: i32 = 74;
+
+fn open(path: &str) -> Result<Box<dyn io::Read>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin()))
+    } else {
+        let f = fs::File::open(path).with_context(|| format!("Failed to open {}", path))?;
+        Ok(Box::new(f))
+    }
+}
+
+fn run() -> Result<()> {
+    let opts = cli::parse_opts()?;
+
+    let f1 = open(&opts.file1)?;
+    let f2 = open(&opts.file2)?;
+    let stats = opts.joiner.join(f1, f2, io::stdout())?;
+
+    match opts.stats {
+        Some(Some(path)) => {
+            let json = format!("{}\n", stats.to_json());
+            fs::write(&path, json).with_context(|| format!("Failed to write {}", path))?
+        }
+        Some(None) => eprintln!("{}", stats),
+        None => (),
+    }
+
+    Ok(())
+}
 
 fn main() {
-    let opts = cli::parse_opts();
-
-    let f1 = fs::File::open(opts.file1).unwrap();
-    let f2 = fs::File::open(opts.file2).unwrap();
-
-    ndjson::join(
-        f1,
-        &opts.key1,
-        f2,
-        &opts.key2,
-        io::stdout(),
-        ndjson::Opts {
-            allow_no_key: opts.allow_no_key,
-            merge: opts.merge,
-        },
-    )
-    .unwrap();
+    if let Err(e) = run() {
+        let io_err = e.chain().find_map(|c| c.downcast_ref::<io::Error>());
+
+        // Exit quietly when the reader of the output has gone (e.g. `jljoin ... | head`)
+        if let Some(err) = io_err {
+            if err.kind() == io::ErrorKind::BrokenPipe {
+                process::exit(0);
+            }
+        }
+
+        eprintln!("jljoin: {:#}", e);
+
+        let code = if e.downcast_ref::<cli::UsageError>().is_some() {
+            EXIT_USAGE
+        } else if io_err.is_some() {
+            EXIT_IO
+        } else {
+            EXIT_DATA
+        };
+
+        process::exit(code);
+    }
 }
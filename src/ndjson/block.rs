@@ -0,0 +1,156 @@
+use super::reader::Keyed;
+use super::source::parse_line;
+use super::source::Record;
+use anyhow::anyhow;
+use anyhow::Result;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::io::Seek;
+use std::io::Write;
+use std::rc::Rc;
+
+// Lines of NDJSON2 that have the same key.
+// The parsed lines are kept in memory up to `limit` bytes (of the JSON text) so that they are parsed
+// only once for all the lines of NDJSON1 with the key. The rest is spilled to a temporary file.
+pub(super) struct Block {
+    key: Value,
+    limit: usize,
+    parse_values: bool,
+    size: usize,
+    recs: Vec<Rc<Keyed>>,
+    spill: Option<io::BufWriter<fs::File>>,
+}
+
+// Position of the replay of a block
+pub(super) struct Cursor {
+    index: usize,
+    spill: Option<io::BufReader<fs::File>>,
+}
+
+impl Block {
+    pub fn new(key: Value, limit: usize, parse_values: bool) -> Self {
+        Block {
+            key,
+            limit,
+            parse_values,
+            size: 0,
+            recs: vec![],
+            spill: None,
+        }
+    }
+
+    pub fn key(&self) -> &Value {
+        &self.key
+    }
+
+    pub fn push(&mut self, rec: Keyed) -> Result<()> {
+        if let Some(ref mut spill) = self.spill {
+            write_positioned(spill, rec.line, rec.offset, &rec.raw)?;
+            return Ok(());
+        }
+
+        if self.size + rec.raw.len() > self.limit && !self.recs.is_empty() {
+            let mut spill = io::BufWriter::new(tempfile::tempfile()?);
+            write_positioned(&mut spill, rec.line, rec.offset, &rec.raw)?;
+            self.spill = Some(spill);
+            return Ok(());
+        }
+
+        self.size += rec.raw.len();
+        self.recs.push(Rc::new(rec));
+        Ok(())
+    }
+
+    // Start replaying the block from the first line.
+    // Only one cursor can be used at a time because the cursors share the spill file.
+    pub fn cursor(&mut self) -> Result<Cursor> {
+        let spill = match self.spill {
+            Some(ref mut spill) => {
+                spill.flush()?;
+                let mut file = spill.get_ref().try_clone()?;
+                file.seek(io::SeekFrom::Start(0))?;
+                Some(io::BufReader::new(file))
+            }
+            None => None,
+        };
+
+        Ok(Cursor { index: 0, spill })
+    }
+
+    // Next line of the block in order
+    pub fn next(&self, cursor: &mut Cursor) -> Result<Option<Rc<Keyed>>> {
+        if let Some(rec) = self.recs.get(cursor.index) {
+            cursor.index += 1;
+            return Ok(Some(Rc::clone(rec)));
+        }
+
+        // The spilled lines are parsed on each replay
+        let (line, offset, raw) = match cursor.spill {
+            Some(ref mut spill) => match read_positioned(spill)? {
+                Some(positioned) => positioned,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+
+        Ok(Some(Rc::new(Keyed {
+            record: Record {
+                json: if self.parse_values {
+                    Some(parse_line(&raw)?)
+                } else {
+                    None
+                },
+                raw,
+                line,
+                offset,
+            },
+            key: self.key.clone(),
+        })))
+    }
+}
+
+pub(super) fn write_line<T>(writer: &mut T, line: &str) -> io::Result<()>
+where
+    T: io::Write,
+{
+    if line.ends_with('\n') {
+        write!(writer, "{}", line)
+    } else {
+        writeln!(writer, "{}", line)
+    }
+}
+
+// Write the line to a temporary file with its position in the input
+pub(super) fn write_positioned<T>(
+    writer: &mut T,
+    line: u64,
+    offset: u64,
+    raw: &str,
+) -> io::Result<()>
+where
+    T: io::Write,
+{
+    write!(writer, "{}\t{}\t", line, offset)?;
+    write_line(writer, raw)
+}
+
+pub(super) fn read_positioned<T>(reader: &mut T) -> Result<Option<(u64, u64, String)>>
+where
+    T: io::BufRead,
+{
+    let mut buf = String::new();
+
+    if reader.read_line(&mut buf)? == 0 {
+        return Ok(None);
+    }
+
+    let mut fields = buf.splitn(3, '\t');
+
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(line), Some(offset), Some(raw)) => {
+            Ok(Some((line.parse()?, offset.parse()?, raw.to_string())))
+        }
+        _ => Err(anyhow!("Broken temporary file: {}", buf.trim_end())),
+    }
+}
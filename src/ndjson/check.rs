@@ -0,0 +1,143 @@
+use super::path::JoinKey;
+use super::MergeStrategy;
+use super::OnConflict;
+use super::Opts;
+use super::OutputShape;
+use anyhow::anyhow;
+use anyhow::Result;
+
+fn check_filenum(filenum: Option<u8>, name: &str) -> Result<()> {
+    match filenum {
+        Some(n) if n != 1 && n != 2 => {
+            Err(anyhow!("File number of {} must be 1 or 2: {}", name, n))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_names((name1, name2): &(String, String), name: &str) -> Result<()> {
+    if name1.is_empty() || name2.is_empty() || name1 == name2 {
+        return Err(anyhow!(
+            "Names of {} must be non-empty and different from each other",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+// Options that cannot be used together
+pub(crate) fn check_opts(opts: &Opts) -> Result<()> {
+    check_filenum(opts.merge, "merging")?;
+    check_filenum(opts.group.as_ref().map(|(n, _)| *n), "grouping")?;
+    check_filenum(opts.only_unmatched, "the anti-join")?;
+    check_filenum(opts.semi, "the semi-join")?;
+    check_filenum(opts.hash, "the hash join")?;
+
+    for (n, paths) in &opts.prefer {
+        check_filenum(Some(*n), "the preferred members")?;
+        JoinKey::parse(paths)?;
+    }
+
+    let prefixed = !opts.prefixes.0.is_empty() || !opts.prefixes.1.is_empty();
+
+    if prefixed && opts.nest.is_some() {
+        return Err(anyhow!("Prefixes and nesting cannot be used together"));
+    }
+
+    if let Some(ref nest) = opts.nest {
+        check_names(nest, "nesting")?;
+    }
+
+    if opts.merge.is_none() {
+        let merge_opts = [
+            (
+                opts.merge_strategy != MergeStrategy::Deep,
+                "The merge strategy",
+            ),
+            (opts.ignore_nulls, "Ignoring nulls"),
+            (prefixed, "Prefixing the members"),
+            (opts.nest.is_some(), "Nesting the members"),
+            (!opts.prefer.is_empty(), "Preferring the members"),
+            (
+                opts.on_conflict != OnConflict::Resolve,
+                "Handling merge conflicts",
+            ),
+        ];
+
+        if let Some((_, name)) = merge_opts.iter().find(|(used, _)| *used) {
+            return Err(anyhow!("{} requires merging", name));
+        }
+    }
+
+    check_names(&opts.names, "the object shape")?;
+
+    if opts.names.0 == "key" || opts.names.1 == "key" {
+        return Err(anyhow!("Names of the object shape must not be 'key'"));
+    }
+
+    if opts.names != Opts::default().names && opts.output_shape != OutputShape::Object {
+        return Err(anyhow!(
+            "Names of the object shape require the object shape"
+        ));
+    }
+
+    if opts.output_shape == OutputShape::Object && (opts.merge.is_some() || !opts.emits_pairs()) {
+        return Err(anyhow!(
+            "The object shape cannot be used with merging, the semi-join or the anti-join"
+        ));
+    }
+
+    if opts.only_unmatched.is_some() && opts.semi.is_some() {
+        return Err(anyhow!(
+            "The anti-join and the semi-join cannot be used together"
+        ));
+    }
+
+    if !opts.emits_pairs() && (opts.merge.is_some() || opts.outer.is_some()) {
+        return Err(anyhow!(
+            "The anti-join and the semi-join cannot be used with merging or the outer join"
+        ));
+    }
+
+    if let Some((n, _)) = opts.group {
+        check_group_opts(n, opts)?;
+    }
+
+    if opts.sort && opts.hash.is_some() {
+        return Err(anyhow!("Sorting and the hash join cannot be used together"));
+    }
+
+    Ok(())
+}
+
+// The rows of a record of the other file must be output while the key is the same (see `Grouper`)
+fn check_group_opts(filenum: u8, opts: &Opts) -> Result<()> {
+    if opts.merge.is_some() || !opts.emits_pairs() || opts.output_shape == OutputShape::Object {
+        return Err(anyhow!(
+            "Grouping cannot be used with merging, the semi-join, the anti-join or the object shape"
+        ));
+    }
+
+    if let Some(outer) = opts.outer {
+        if outer.includes(filenum) {
+            return Err(anyhow!(
+                "Grouping the records of FILE{} cannot output the unpaired records of FILE{}",
+                filenum,
+                filenum
+            ));
+        }
+    }
+
+    if let Some(n) = opts.hash {
+        if n != filenum {
+            return Err(anyhow!(
+                "Grouping the records of FILE{} requires loading FILE{} into memory",
+                filenum,
+                filenum
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use super::ord::compare_keys;
+use super::source::Record;
+use anyhow::Result;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io;
+
+// Record with the matched records of the other file
+struct Group {
+    base: Record,
+    items: Vec<Value>,
+}
+
+// Collector of the matched records of the file `filenum` into the member `name`
+// of the record of the other file (`--group`).
+// The rows of a record of the other file must be output while the key is the same.
+pub(super) struct Grouper {
+    filenum: u8,
+    name: String,
+    key: Option<Value>,
+    groups: Vec<Group>,
+    // Index of the group by the position of the record
+    index: HashMap<(u64, u64), usize>,
+    output_rows: u64,
+}
+
+impl Grouper {
+    pub fn new(filenum: u8, name: &str) -> Self {
+        assert!(filenum == 1 || filenum == 2);
+
+        Grouper {
+            filenum,
+            name: name.to_string(),
+            key: None,
+            groups: vec![],
+            index: HashMap::new(),
+            output_rows: 0,
+        }
+    }
+
+    pub fn write<W>(
+        &mut self,
+        writer: &mut W,
+        key: &Value,
+        left: Option<&Record>,
+        right: Option<&Record>,
+    ) -> Result<()>
+    where
+        W: io::Write,
+    {
+        let (base, item) = if self.filenum == 1 {
+            (right, left)
+        } else {
+            (left, right)
+        };
+
+        // The unpaired records of the file are not output
+        let base = match base {
+            Some(base) => base,
+            None => return Ok(()),
+        };
+
+        match self.key {
+            Some(ref k) if compare_keys(k, key) == Ordering::Equal => (),
+            _ => {
+                self.flush(writer)?;
+                self.key = Some(key.clone());
+            }
+        }
+
+        let groups = &mut self.groups;
+
+        let i = *self
+            .index
+            .entry((base.line, base.offset))
+            .or_insert_with(|| {
+                groups.push(Group {
+                    base: base.clone(),
+                    items: vec![],
+                });
+
+                groups.len() - 1
+            });
+
+        if let Some(item) = item {
+            self.groups[i].items.push(item.value()?.into_owned());
+        }
+
+        Ok(())
+    }
+
+    // Number of the lines written
+    pub fn output_rows(&self) -> u64 {
+        self.output_rows
+    }
+
+    // Output the records of the current key
+    pub fn flush<W>(&mut self, writer: &mut W) -> Result<()>
+    where
+        W: io::Write,
+    {
+        for group in self.groups.drain(..) {
+            let mut json = group.base.into_value()?;
+
+            if let Value::Object(ref mut m) = json {
+                m.insert(self.name.clone(), Value::Array(group.items));
+            }
+
+            writeln!(writer, "{}", json)?;
+            self.output_rows += 1;
+        }
+
+        self.index.clear();
+        Ok(())
+    }
+}
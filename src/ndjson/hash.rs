@@ -0,0 +1,224 @@
+use super::ord::canonical_key;
+use super::reader::Keyed;
+use super::reader::Source;
+use super::rows::Engine;
+use super::rows::Row;
+use super::stats::FileStats;
+use super::stats::Stats;
+use super::Opts;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+struct Entry {
+    rec: Rc<Keyed>,
+    matched: bool,
+}
+
+// Lines of the build side indexed by the canonical key
+struct Table {
+    entries: Vec<Entry>,
+    index: HashMap<String, Vec<usize>>,
+}
+
+fn load(source: &mut dyn Source) -> Result<Table> {
+    let mut table = Table {
+        entries: vec![],
+        index: HashMap::new(),
+    };
+
+    while let Some(rec) = source.read()? {
+        table
+            .index
+            .entry(canonical_key(&rec.key))
+            .or_default()
+            .push(table.entries.len());
+
+        table.entries.push(Entry {
+            rec: Rc::new(rec),
+            matched: false,
+        });
+    }
+
+    Ok(table)
+}
+
+enum State {
+    Load,
+    // Read the next line of the probe side
+    Probe,
+    // Pair the line of the probe side with the lines of the build side with the key
+    Matches(Rc<Keyed>, String, usize),
+    // Output the unpaired lines of the build side
+    Unmatched(usize),
+    Done,
+}
+
+// Join by loading the lines of the file `build_filenum` into memory.
+// The inputs do not need to be sorted.
+pub(super) struct HashJoin<'a> {
+    build: Box<dyn Source + 'a>,
+    probe: Box<dyn Source + 'a>,
+    build_filenum: u8,
+    opts: Opts,
+    table: Table,
+    state: State,
+}
+
+impl<'a> HashJoin<'a> {
+    pub fn new(
+        source1: Box<dyn Source + 'a>,
+        source2: Box<dyn Source + 'a>,
+        build_filenum: u8,
+        opts: &Opts,
+    ) -> Self {
+        assert!(build_filenum == 1 || build_filenum == 2);
+
+        let (build, probe) = if build_filenum == 1 {
+            (source1, source2)
+        } else {
+            (source2, source1)
+        };
+
+        HashJoin {
+            build,
+            probe,
+            build_filenum,
+            opts: opts.clone(),
+            table: Table {
+                entries: vec![],
+                index: HashMap::new(),
+            },
+            state: State::Load,
+        }
+    }
+
+    // Row of the line of the probe side and the line of the build side
+    fn row(&self, probe: Option<Rc<Keyed>>, build: Option<Rc<Keyed>>) -> Row {
+        if self.build_filenum == 1 {
+            Row {
+                left: build,
+                right: probe,
+            }
+        } else {
+            Row {
+                left: probe,
+                right: build,
+            }
+        }
+    }
+}
+
+impl<'a> Engine for HashJoin<'a> {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        let filenum = 3 - self.build_filenum;
+
+        loop {
+            match self.state {
+                State::Load => {
+                    self.table = load(&mut *self.build)?;
+                    self.state = State::Probe;
+                }
+                State::Probe => {
+                    let rec = match self.probe.read()? {
+                        Some(rec) => Rc::new(rec),
+                        None => {
+                            self.state = State::Unmatched(0);
+                            continue;
+                        }
+                    };
+
+                    let key = canonical_key(&rec.key);
+
+                    if !self.table.index.contains_key(&key) {
+                        if self.opts.outputs_unmatched(filenum) {
+                            return Ok(Some(self.row(Some(rec), None)));
+                        }
+
+                        continue;
+                    }
+
+                    self.state = State::Matches(Rc::clone(&rec), key, 0);
+
+                    if self.opts.semi == Some(filenum) {
+                        return Ok(Some(self.row(Some(rec), None)));
+                    }
+                }
+                State::Matches(ref rec, ref key, ref mut pos) => {
+                    let i = match self.table.index[key].get(*pos) {
+                        Some(&i) => i,
+                        None => {
+                            self.state = State::Probe;
+                            continue;
+                        }
+                    };
+
+                    *pos += 1;
+                    let rec = Rc::clone(rec);
+                    let entry = &mut self.table.entries[i];
+                    let first_match = !entry.matched;
+                    entry.matched = true;
+                    let build = Rc::clone(&entry.rec);
+
+                    if self.opts.emits_pairs() {
+                        return Ok(Some(self.row(Some(rec), Some(build))));
+                    } else if self.opts.semi == Some(self.build_filenum) && first_match {
+                        return Ok(Some(self.row(None, Some(build))));
+                    }
+                }
+                State::Unmatched(ref mut pos) => {
+                    let entry = match self.table.entries.get(*pos) {
+                        Some(entry) => entry,
+                        None => {
+                            self.state = State::Done;
+                            continue;
+                        }
+                    };
+
+                    *pos += 1;
+
+                    if !entry.matched && self.opts.outputs_unmatched(self.build_filenum) {
+                        let build = Rc::clone(&entry.rec);
+                        return Ok(Some(self.row(None, Some(build))));
+                    }
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let table = &self.table;
+
+        let matched_keys = table
+            .index
+            .values()
+            .filter(|idxs| table.entries[idxs[0]].matched)
+            .count() as u64;
+
+        let build_stats = FileStats {
+            lines: self.build.lines(),
+            distinct_keys: Some(table.index.len() as u64),
+            unmatched_keys: Some(table.index.len() as u64 - matched_keys),
+            largest_block: table.index.values().map(|idxs| idxs.len() as u64).max(),
+        };
+
+        let probe_stats = FileStats {
+            lines: self.probe.lines(),
+            ..Default::default()
+        };
+
+        let (file1, file2) = if self.build_filenum == 1 {
+            (build_stats, probe_stats)
+        } else {
+            (probe_stats, build_stats)
+        };
+
+        Stats {
+            file1,
+            file2,
+            matched_keys,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use super::block::Block;
+use super::block::Cursor;
+use super::ord::compare_keys;
+use super::reader::truncate;
+use super::reader::Keyed;
+use super::reader::Source;
+use super::rows::Engine;
+use super::rows::Row;
+use super::stats::Stats;
+use super::Opts;
+use anyhow::anyhow;
+use anyhow::Result;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+// Order check and key statistics of a sorted input
+struct KeyTracker {
+    name: String,
+    check_order: bool,
+    prev_key: Option<Value>,
+    distinct_keys: u64,
+    block_len: u64,
+    largest_block: u64,
+}
+
+impl KeyTracker {
+    fn new(name: &str, check_order: bool) -> Self {
+        KeyTracker {
+            name: name.to_string(),
+            check_order,
+            prev_key: None,
+            distinct_keys: 0,
+            block_len: 0,
+            largest_block: 0,
+        }
+    }
+
+    fn track(&mut self, rec: &Keyed) -> Result<()> {
+        let ord = match self.prev_key {
+            Some(ref prev_key) => compare_keys(prev_key, &rec.key),
+            None => Ordering::Less,
+        };
+
+        // Data errors are reported with the location of the line (file:line:offset)
+        if self.check_order && ord == Ordering::Greater {
+            return Err(anyhow!(
+                "{}:{}:{}: Not sorted by the join key: {} comes after {}",
+                self.name,
+                rec.line,
+                rec.offset,
+                truncate(&rec.key.to_string()),
+                truncate(&self.prev_key.as_ref().unwrap().to_string())
+            ));
+        }
+
+        if ord == Ordering::Equal {
+            self.block_len += 1;
+        } else {
+            self.distinct_keys += 1;
+            self.block_len = 1;
+        }
+
+        self.largest_block = self.largest_block.max(self.block_len);
+        self.prev_key = Some(rec.key.clone());
+        Ok(())
+    }
+}
+
+enum State {
+    Start,
+    // Compare the current lines of NDJSON1 and NDJSON2
+    Scan,
+    // Read the next line of NDJSON1 (or NDJSON2) after the output of the current line
+    Advance(u8),
+    // Output the block for `--semi 2`
+    Semi(Cursor),
+    // Take the next line of NDJSON1 with the key of the block
+    Group,
+    // Read the next line of NDJSON1 after the output of the line in the group
+    GroupAdvance,
+    // Output the current line of NDJSON1 paired with each line of the block
+    Pairs(Cursor),
+    Done,
+}
+
+// Sort-merge join of the inputs sorted by the join key
+pub(super) struct MergeJoin<'a> {
+    source1: Box<dyn Source + 'a>,
+    source2: Box<dyn Source + 'a>,
+    tracker1: KeyTracker,
+    tracker2: KeyTracker,
+    opts: Opts,
+    parse_values: bool,
+    state: State,
+    curt1: Option<Keyed>,
+    curt2: Option<Keyed>,
+    left: Option<Rc<Keyed>>,
+    block: Option<Block>,
+    matched_keys: u64,
+}
+
+impl<'a> MergeJoin<'a> {
+    pub fn new(
+        source1: Box<dyn Source + 'a>,
+        source2: Box<dyn Source + 'a>,
+        check_order: bool,
+        parse_values: bool,
+        opts: &Opts,
+    ) -> Self {
+        let (ref name1, ref name2) = opts.file_names;
+
+        MergeJoin {
+            source1,
+            source2,
+            tracker1: KeyTracker::new(name1, check_order),
+            tracker2: KeyTracker::new(name2, check_order),
+            opts: opts.clone(),
+            parse_values,
+            state: State::Start,
+            curt1: None,
+            curt2: None,
+            left: None,
+            block: None,
+            matched_keys: 0,
+        }
+    }
+
+    fn read1(&mut self) -> Result<()> {
+        self.curt1 = self.source1.read()?;
+
+        if let Some(ref rec1) = self.curt1 {
+            self.tracker1.track(rec1)?;
+        }
+
+        Ok(())
+    }
+
+    fn read2(&mut self) -> Result<()> {
+        self.curt2 = self.source2.read()?;
+
+        if let Some(ref rec2) = self.curt2 {
+            self.tracker2.track(rec2)?;
+        }
+
+        Ok(())
+    }
+
+    // Read the current block in NDJSON2
+    fn read_block(&mut self) -> Result<Block> {
+        let rec2 = self.curt2.take().unwrap();
+        let mut block = Block::new(
+            rec2.key.clone(),
+            self.opts.block_buffer_size,
+            self.parse_values,
+        );
+        block.push(rec2)?;
+
+        loop {
+            self.read2()?;
+
+            match self.curt2.take() {
+                Some(rec2) if compare_keys(&rec2.key, block.key()) == Ordering::Equal => {
+                    block.push(rec2)?
+                }
+                rec2 => {
+                    self.curt2 = rec2;
+                    break;
+                }
+            }
+        }
+
+        Ok(block)
+    }
+}
+
+impl<'a> Engine for MergeJoin<'a> {
+    fn next_row(&mut self) -> Result<Option<Row>> {
+        loop {
+            match self.state {
+                State::Start => {
+                    self.read1()?;
+                    self.read2()?;
+                    self.state = State::Scan;
+                }
+                State::Scan => {
+                    let ord = match (&self.curt1, &self.curt2) {
+                        (None, None) => {
+                            self.state = State::Done;
+                            continue;
+                        }
+                        (Some(_), None) => Ordering::Less,
+                        (None, Some(_)) => Ordering::Greater,
+                        (Some(rec1), Some(rec2)) => compare_keys(&rec1.key, &rec2.key),
+                    };
+
+                    match ord {
+                        Ordering::Less => {
+                            // NDJSON1 is behind: the line has no partner
+                            self.state = State::Advance(1);
+                            let rec1 = self.curt1.take().unwrap();
+
+                            if self.opts.outputs_unmatched(1) {
+                                return Ok(Some(Row {
+                                    left: Some(Rc::new(rec1)),
+                                    right: None,
+                                }));
+                            }
+                        }
+                        Ordering::Greater => {
+                            // NDJSON2 is behind: the line has no partner
+                            self.state = State::Advance(2);
+                            let rec2 = self.curt2.take().unwrap();
+
+                            if self.opts.outputs_unmatched(2) {
+                                return Ok(Some(Row {
+                                    left: None,
+                                    right: Some(Rc::new(rec2)),
+                                }));
+                            }
+                        }
+                        Ordering::Equal => {
+                            self.matched_keys += 1;
+                            let mut block = self.read_block()?;
+
+                            self.state = if self.opts.semi == Some(2) {
+                                State::Semi(block.cursor()?)
+                            } else {
+                                State::Group
+                            };
+
+                            self.block = Some(block);
+                        }
+                    }
+                }
+                State::Advance(filenum) => {
+                    if filenum == 1 {
+                        self.read1()?;
+                    } else {
+                        self.read2()?;
+                    }
+
+                    self.state = State::Scan;
+                }
+                State::Semi(ref mut cursor) => {
+                    let block = self.block.as_ref().unwrap();
+
+                    match block.next(cursor)? {
+                        Some(rec2) => {
+                            return Ok(Some(Row {
+                                left: None,
+                                right: Some(rec2),
+                            }))
+                        }
+                        None => self.state = State::Group,
+                    }
+                }
+                State::Group => {
+                    let block = self.block.as_mut().unwrap();
+
+                    let rec1 = match self.curt1.take() {
+                        Some(rec1) if compare_keys(&rec1.key, block.key()) == Ordering::Equal => {
+                            Rc::new(rec1)
+                        }
+                        rec1 => {
+                            self.curt1 = rec1;
+                            self.block = None;
+                            self.state = State::Scan;
+                            continue;
+                        }
+                    };
+
+                    if self.opts.semi == Some(1) {
+                        self.state = State::GroupAdvance;
+                        return Ok(Some(Row {
+                            left: Some(rec1),
+                            right: None,
+                        }));
+                    }
+
+                    if self.opts.emits_pairs() {
+                        // Repeat the current block in NDJSON2
+                        self.state = State::Pairs(block.cursor()?);
+                        self.left = Some(rec1);
+                    } else {
+                        self.state = State::GroupAdvance;
+                    }
+                }
+                State::GroupAdvance => {
+                    self.read1()?;
+                    self.state = State::Group;
+                }
+                State::Pairs(ref mut cursor) => {
+                    let block = self.block.as_ref().unwrap();
+
+                    match block.next(cursor)? {
+                        Some(rec2) => {
+                            return Ok(Some(Row {
+                                left: self.left.clone(),
+                                right: Some(rec2),
+                            }))
+                        }
+                        None => {
+                            self.left = None;
+                            self.state = State::GroupAdvance;
+                        }
+                    }
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            matched_keys: self.matched_keys,
+            ..Default::default()
+        };
+
+        for (file, source, tracker) in [
+            (&mut stats.file1, &self.source1, &self.tracker1),
+            (&mut stats.file2, &self.source2, &self.tracker2),
+        ] {
+            file.lines = source.lines();
+            file.distinct_keys = Some(tracker.distinct_keys);
+            file.unmatched_keys = Some(tracker.distinct_keys - self.matched_keys);
+            file.largest_block = Some(tracker.largest_block);
+        }
+
+        stats
+    }
+}
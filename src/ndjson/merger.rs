@@ -0,0 +1,268 @@
+use super::ord::compare_keys;
+use super::path::JoinKey;
+use super::path::KeyPath;
+use super::reader::truncate;
+use super::source::Record;
+use super::MergeStrategy;
+use super::OnConflict;
+use super::Opts;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::io::Write;
+
+// Member of the paired JSON with different values
+struct Conflict {
+    path: String,
+    left: Value,
+    right: Value,
+}
+
+// Merger of the paired JSON by `--merge` and `--merge-strategy`
+pub(super) struct Merger {
+    priority: u8,
+    strategy: MergeStrategy,
+    ignore_nulls: bool,
+    // Paths of the members with the priority file
+    prefer: Vec<(u8, KeyPath)>,
+    prefixes: (String, String),
+    nest: Option<(String, String)>,
+    // Top-level members of the join keys of FILE1 and FILE2 (not prefixed or nested)
+    key_members: (Vec<String>, Vec<String>),
+    on_conflict: OnConflict,
+    file_names: (String, String),
+    conflicts: u64,
+    report: Option<io::BufWriter<fs::File>>,
+}
+
+impl Merger {
+    pub fn new(priority: u8, opts: &Opts, key_members: (Vec<String>, Vec<String>)) -> Result<Self> {
+        assert!(priority == 1 || priority == 2);
+
+        let report = match opts.on_conflict {
+            OnConflict::Report(ref path) => {
+                let f = fs::File::create(path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?;
+                Some(io::BufWriter::new(f))
+            }
+            _ => None,
+        };
+
+        let mut prefer = vec![];
+
+        for (filenum, paths) in &opts.prefer {
+            for path in JoinKey::parse(paths)?.paths() {
+                prefer.push((*filenum, path.clone()));
+            }
+        }
+
+        Ok(Merger {
+            priority,
+            strategy: opts.merge_strategy,
+            ignore_nulls: opts.ignore_nulls,
+            prefer,
+            prefixes: opts.prefixes.clone(),
+            nest: opts.nest.clone(),
+            key_members,
+            on_conflict: opts.on_conflict.clone(),
+            file_names: opts.file_names.clone(),
+            conflicts: 0,
+            report,
+        })
+    }
+
+    // Merge the JSON of `rec2` into the JSON of `rec1`.
+    // The members of `rec1` keep their order and the other members of `rec2` follow in their order.
+    pub fn merge(&mut self, key: &Value, rec1: &Record, rec2: &Record) -> Result<Value> {
+        let mut out_json = self.shape(rec1.value()?.into_owned(), 1);
+        let json2 = self.shape(rec2.value()?.into_owned(), 2);
+        let mut conflicts = vec![];
+        self.merge_value(
+            &mut out_json,
+            &json2,
+            self.priority,
+            &mut vec![],
+            &mut conflicts,
+        );
+
+        for c in conflicts {
+            self.conflicts += 1;
+
+            if self.on_conflict == OnConflict::Fail {
+                return Err(anyhow!(
+                    "{}:{}:{}: Merge conflict at '{}' with {}:{}:{}: {} and {}",
+                    self.file_names.0,
+                    rec1.line,
+                    rec1.offset,
+                    c.path,
+                    self.file_names.1,
+                    rec2.line,
+                    rec2.offset,
+                    truncate(&c.left.to_string()),
+                    truncate(&c.right.to_string())
+                ));
+            }
+
+            if let Some(ref mut writer) = self.report {
+                let conflict = json!({
+                    "key": key,
+                    "path": c.path,
+                    "left": c.left,
+                    "right": c.right,
+                });
+
+                writeln!(writer, "{}", conflict)?;
+            }
+        }
+
+        Ok(out_json)
+    }
+
+    // Prefix or nest the members of the JSON of the file except the members of the join key
+    pub fn shape(&self, json: Value, filenum: u8) -> Value {
+        let (prefix, key_members, nest) = if filenum == 1 {
+            (
+                &self.prefixes.0,
+                &self.key_members.0,
+                self.nest.as_ref().map(|n| &n.0),
+            )
+        } else {
+            (
+                &self.prefixes.1,
+                &self.key_members.1,
+                self.nest.as_ref().map(|n| &n.1),
+            )
+        };
+
+        let members = match json {
+            Value::Object(m) if !prefix.is_empty() || nest.is_some() => m,
+            _ => return json,
+        };
+
+        let mut out = Map::new();
+        let mut nested = Map::new();
+
+        for (k, v) in members {
+            if key_members.contains(&k) {
+                out.insert(k, v);
+            } else if nest.is_some() {
+                nested.insert(k, v);
+            } else {
+                out.insert(format!("{}{}", prefix, k), v);
+            }
+        }
+
+        if let Some(name) = nest {
+            out.insert(name.clone(), Value::Object(nested));
+        }
+
+        Value::Object(out)
+    }
+
+    // Number of the members with different values in the merged JSON
+    pub fn conflicts(&self) -> u64 {
+        self.conflicts
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(ref mut writer) = self.report {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    // Priority file of the member at the path, or `None` if there is no rule for the path
+    fn preferred(&self, path: &[&str]) -> Option<u8> {
+        self.prefer
+            .iter()
+            .rev()
+            .find(|(_, p)| p.matches(path))
+            .map(|(filenum, _)| *filenum)
+    }
+
+    fn merge_value<'a>(
+        &self,
+        a: &mut Value,
+        b: &'a Value,
+        priority: u8,
+        path: &mut Vec<&'a str>,
+        conflicts: &mut Vec<Conflict>,
+    ) {
+        match (a, b) {
+            (Value::Object(a), Value::Object(b))
+                if path.is_empty() || self.strategy != MergeStrategy::Shallow =>
+            {
+                for (k, v) in b {
+                    match a.get_mut(k) {
+                        Some(a) => {
+                            path.push(k);
+                            let priority = self.preferred(path).unwrap_or(priority);
+                            self.merge_value(a, v, priority, path, conflicts);
+                            path.pop();
+                        }
+                        None => {
+                            a.insert(k.clone(), v.clone());
+                        }
+                    }
+                }
+            }
+            (Value::Array(a), Value::Array(b)) if self.strategy == MergeStrategy::ArrayConcat => {
+                a.extend(b.iter().cloned());
+            }
+            (Value::Array(a), Value::Array(b)) if self.strategy == MergeStrategy::ArrayUnion => {
+                for v in b {
+                    if !a.iter().any(|x| compare_keys(x, v) == Ordering::Equal) {
+                        a.push(v.clone());
+                    }
+                }
+            }
+            (a, b) => {
+                let overwrite = if self.ignore_nulls && (a.is_null() || b.is_null()) {
+                    a.is_null()
+                } else {
+                    if compare_keys(a, b) != Ordering::Equal {
+                        conflicts.push(Conflict {
+                            path: format_path(path),
+                            left: a.clone(),
+                            right: b.clone(),
+                        });
+                    }
+
+                    priority == 2
+                };
+
+                if overwrite {
+                    *a = b.clone();
+                }
+            }
+        }
+    }
+}
+
+// Dotted path of the member (`.`, `[`, `]` and `\` are escaped as in the key)
+fn format_path(path: &[&str]) -> String {
+    let mut s = String::new();
+
+    for (i, k) in path.iter().enumerate() {
+        if i > 0 {
+            s.push('.');
+        }
+
+        for c in k.chars() {
+            if let '.' | '[' | ']' | '\\' = c {
+                s.push('\\');
+            }
+
+            s.push(c);
+        }
+    }
+
+    s
+}
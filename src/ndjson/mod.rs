@@ -1,169 +1,320 @@
+mod block;
+mod check;
+mod group;
+mod hash;
+mod merge;
+mod merger;
+mod ord;
+mod path;
+mod reader;
+mod reject;
+mod rows;
+mod sink;
+mod sort;
+mod source;
+mod stats;
 #[cfg(test)]
 mod tests;
 
 use anyhow::anyhow;
 use anyhow::Result;
+pub(crate) use check::check_opts;
+use hash::HashJoin;
+use merge::MergeJoin;
+use path::JoinKey;
+use reader::Reader;
+use reject::ErrorHandler;
+use rows::Engine;
+pub use rows::JoinedRow;
+pub use rows::Position;
+pub use rows::Rows;
 use serde_json::json;
 use serde_json::Value;
-use std::fs;
+pub use sink::NdjsonSink;
+pub use sink::RecordSink;
+use sort::Sorted;
+pub use source::MalformedRecord;
+pub use source::NdjsonSource;
+pub use source::Record;
+pub use source::RecordSource;
+pub use stats::FileStats;
+pub use stats::Stats;
 use std::io;
-use std::io::Seek;
+use std::path::PathBuf;
+use std::rc::Rc;
 
-pub(super) struct Opts {
-    pub allow_no_key: bool,
-    pub merge: Option<u8>,
+/// Which unpaired lines to output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outer {
+    Left,
+    Right,
+    Full,
 }
 
-pub(super) fn join<T>(
-    file1: fs::File,
-    key1: &str,
-    file2: fs::File,
-    key2: &str,
-    fout: T,
-    opts: Opts,
-) -> Result<()>
-where
-    T: io::Write,
-{
-    let mut reader1 = io::BufReader::new(file1);
-    let mut reader2 = io::BufReader::new(file2);
-    let mut writer = io::BufWriter::new(fout);
+impl Outer {
+    fn includes(self, filenum: u8) -> bool {
+        matches!(
+            (self, filenum),
+            (Outer::Left, 1) | (Outer::Right, 2) | (Outer::Full, _)
+        )
+    }
+}
 
-    let mut prev1 = None;
-    let mut block_start: u64 = 0;
-    let mut block_end: u64 = 0;
+/// How to merge the paired JSON.
+///
+/// The value of the priority file wins unless the values are merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    /// Merge the top-level members only
+    Shallow,
+    /// Merge the objects recursively
+    Deep,
+    /// Merge the objects recursively and concatenate the arrays (FILE1 first)
+    ArrayConcat,
+    /// Merge the objects recursively and append the elements of the arrays of FILE2 not in FILE1
+    ArrayUnion,
+}
 
-    loop {
-        let curt1 = read_line_with_parsing(&mut reader1)?;
+/// How to handle the members of the paired JSON with different values when merging.
+///
+/// The conflicts are counted in [`Stats::merge_conflicts`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnConflict {
+    /// Keep the value by the merge strategy
+    Resolve,
+    /// Keep the value by the merge strategy and write the conflict to the file as NDJSON
+    Report(PathBuf),
+    /// Fail the join
+    Fail,
+}
 
-        if curt1.is_none() {
-            break;
-        }
+/// Shape of the output of the paired lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputShape {
+    /// `[json1,json2]`
+    Array,
+    /// `{"key":KEY,"left":json1,"right":json2}` with the names of the sides
+    Object,
+}
 
-        let json1 = curt1.as_ref().unwrap();
-        let val1 = json_get_or_err(json1, key1, opts.allow_no_key)?;
+/// How to handle malformed lines (invalid JSON, non-object JSON or no key).
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnError {
+    /// Fail the join
+    Fail,
+    /// Drop the line with a warning on stderr
+    Skip,
+    /// Write the line to the file as NDJSON with its origin
+    Quarantine(PathBuf),
+}
 
-        if let Some(ref prev_json1) = prev1 {
-            let prev_val1 = json_get_or_err(prev_json1, key1, opts.allow_no_key)?;
+#[derive(Debug, Clone)]
+pub(crate) struct Opts {
+    pub allow_no_key: bool,
+    pub merge: Option<u8>,
+    pub merge_strategy: MergeStrategy,
+    pub ignore_nulls: bool,
+    pub prefixes: (String, String),
+    pub nest: Option<(String, String)>,
+    pub on_conflict: OnConflict,
+    // Paths of the members to prefer the file (1 or 2) when merging
+    pub prefer: Vec<(u8, String)>,
+    // Collect the matched records of the file (1 or 2) into the member of the record of the other file
+    pub group: Option<(u8, String)>,
+    pub outer: Option<Outer>,
+    pub placeholder: Value,
+    pub output_shape: OutputShape,
+    pub names: (String, String),
+    pub only_unmatched: Option<u8>,
+    pub semi: Option<u8>,
+    pub check_order: bool,
+    pub file_names: (String, String),
+    pub block_buffer_size: usize,
+    pub hash: Option<u8>,
+    pub sort: bool,
+    pub memory_limit: usize,
+    pub on_error: OnError,
+    pub max_errors: Option<u64>,
+}
 
-            if val1 != prev_val1 {
-                // Go to the next block in NDJSON2
-                reader2.seek(io::SeekFrom::Start(block_end))?;
-                block_start = block_end
-            } else {
-                // Repeat the current block in NDJSON2
-                reader2.seek(io::SeekFrom::Start(block_start))?;
-            }
+impl Default for Opts {
+    fn default() -> Self {
+        Opts {
+            allow_no_key: false,
+            merge: None,
+            merge_strategy: MergeStrategy::Deep,
+            ignore_nulls: false,
+            prefixes: (String::new(), String::new()),
+            nest: None,
+            on_conflict: OnConflict::Resolve,
+            prefer: vec![],
+            group: None,
+            outer: None,
+            placeholder: json!(null),
+            output_shape: OutputShape::Array,
+            names: ("left".to_string(), "right".to_string()),
+            only_unmatched: None,
+            semi: None,
+            check_order: true,
+            file_names: ("FILE1".to_string(), "FILE2".to_string()),
+            block_buffer_size: 64 * 1024 * 1024,
+            hash: None,
+            sort: false,
+            memory_limit: 256 * 1024 * 1024,
+            on_error: OnError::Fail,
+            max_errors: None,
         }
+    }
+}
 
-        let mut prev2 = None;
-
-        loop {
-            block_end = reader2.stream_position()?;
-            let curt2 = read_line_with_parsing(&mut reader2)?;
-
-            if curt2.is_none() {
-                break;
-            }
-
-            let json2 = curt2.as_ref().unwrap();
-            let val2 = json_get_or_err(json2, key2, opts.allow_no_key)?;
-
-            if let Some(ref prev_json2) = prev2 {
-                let prev_val2 = json_get_or_err(prev_json2, key2, opts.allow_no_key)?;
-
-                if val2 != prev_val2 {
-                    break;
-                }
-            }
-
-            if val1 == val2 {
-                print_pair(&mut writer, &json1, json2, opts.merge)?;
-            }
+impl Opts {
+    fn emits_pairs(&self) -> bool {
+        self.only_unmatched.is_none() && self.semi.is_none()
+    }
 
-            prev2 = curt2;
+    // Whether to output the unpaired lines of the file
+    fn outputs_unmatched(&self, filenum: u8) -> bool {
+        match (self.only_unmatched, self.outer) {
+            (Some(n), _) => n == filenum,
+            (None, Some(outer)) => outer.includes(filenum),
+            (None, None) => false,
         }
-
-        prev1 = curt1;
     }
-
-    Ok(())
 }
 
-fn read_line_with_parsing<T>(reader: &mut T) -> Result<Option<Value>>
+pub(crate) fn rows<'a, R1, R2>(
+    file1: R1,
+    key1: &str,
+    file2: R2,
+    key2: &str,
+    opts: Opts,
+) -> Result<Rows<'a>>
 where
-    T: io::BufRead,
+    R1: io::Read + 'a,
+    R2: io::Read + 'a,
 {
-    let mut line = String::new();
-    let n = reader.read_line(&mut line)?;
+    let source1 = Box::new(NdjsonSource::new(file1));
+    let source2 = Box::new(NdjsonSource::new(file2));
+    rows_from(source1, key1, source2, key2, opts, true)
+}
 
-    if n == 0 {
-        return Ok(None);
+// Parse the keys of FILE1 and FILE2, which must have the same number of paths
+fn parse_keys(key1: &str, key2: &str) -> Result<(JoinKey, JoinKey)> {
+    let key1 = JoinKey::parse(key1)?;
+    let key2 = JoinKey::parse(key2)?;
+
+    if key1.paths().len() != key2.paths().len() {
+        return Err(anyhow!(
+            "Number of join keys does not match: '{}' and '{}'",
+            key1,
+            key2
+        ));
     }
 
-    let r: serde_json::error::Result<Value> = serde_json::from_str(&line);
+    Ok((key1, key2))
+}
 
-    match r {
-        Err(e) => {
-            let ctx = format!("Failed to parse JSON: {}", &line);
-            Err(anyhow::Error::new(e).context(ctx))
-        }
-        Ok(v) => {
-            if !v.is_object() {
-                Err(anyhow!("JSON in row is not Object type: {}", v))
-            } else {
-                Ok(Some(v))
-            }
-        }
-    }
+pub(crate) fn check_keys(key1: &str, key2: &str) -> Result<()> {
+    parse_keys(key1, key2).map(|_| ())
 }
 
-// NOTE: Copy from https://github.com/serde-rs/json/issues/377#issuecomment-341490464
-fn merge_obj(a: &mut Value, b: &Value) {
-    match (a, b) {
-        (&mut Value::Object(ref mut a), &Value::Object(ref b)) => {
-            for (k, v) in b {
-                merge_obj(a.entry(k.clone()).or_insert(Value::Null), v);
-            }
-        }
-        (a, b) => {
-            *a = b.clone();
-        }
-    }
+// Rows of the join of the sources.
+// Only the keys are parsed from the records of NDJSON unless `parse_values` is true.
+pub(crate) fn rows_from<'a>(
+    source1: Box<dyn RecordSource + 'a>,
+    key1: &str,
+    source2: Box<dyn RecordSource + 'a>,
+    key2: &str,
+    opts: Opts,
+    parse_values: bool,
+) -> Result<Rows<'a>> {
+    check_opts(&opts)?;
+    let (key1, key2) = parse_keys(key1, key2)?;
+    let errors = Rc::new(ErrorHandler::new(&opts.on_error, opts.max_errors)?);
+    let (ref name1, ref name2) = opts.file_names;
+    let allow_no_key = opts.allow_no_key;
+    // The sorted lines are parsed again when they are read back, so the lines buffered
+    // for sorting only have the key (their size is limited by `memory_limit`)
+    let read_values = parse_values && !opts.sort;
+    let reader1 = Reader::new(source1, name1, &key1, allow_no_key, read_values, &errors);
+    let reader2 = Reader::new(source2, name2, &key2, allow_no_key, read_values, &errors);
+    let (reader1, reader2) = (Box::new(reader1), Box::new(reader2));
+
+    let engine: Box<dyn Engine> = if let Some(n) = opts.hash {
+        Box::new(HashJoin::new(reader1, reader2, n, &opts))
+    } else if opts.sort {
+        let memory_limit = opts.memory_limit;
+        let sorted1 = Sorted::new(reader1, &key1, allow_no_key, parse_values, memory_limit)?;
+        let sorted2 = Sorted::new(reader2, &key2, allow_no_key, parse_values, memory_limit)?;
+        Box::new(MergeJoin::new(
+            Box::new(sorted1),
+            Box::new(sorted2),
+            true,
+            parse_values,
+            &opts,
+        ))
+    } else {
+        let check_order = opts.check_order;
+        Box::new(MergeJoin::new(
+            reader1,
+            reader2,
+            check_order,
+            parse_values,
+            &opts,
+        ))
+    };
+
+    Ok(Rows::new(engine, errors))
 }
 
-fn print_pair<T>(writer: &mut T, json1: &Value, json2: &Value, merge: Option<u8>) -> io::Result<()>
+pub(crate) fn join<R1, R2, T>(
+    file1: R1,
+    key1: &str,
+    file2: R2,
+    key2: &str,
+    fout: T,
+    opts: Opts,
+) -> Result<Stats>
 where
+    R1: io::Read,
+    R2: io::Read,
     T: io::Write,
 {
-    let line = if let Some(n) = merge {
-        assert!(n == 1 || n == 2);
-
-        if n == 1 {
-            let mut out_json = json2.clone();
-            merge_obj(&mut out_json, &json1);
-            out_json
-        } else {
-            let mut out_json = json1.clone();
-            merge_obj(&mut out_json, &json2);
-            out_json
-        }
-        .to_string()
-    } else {
-        format!("[{},{}]", json1, json2)
-    };
+    let source1 = Box::new(NdjsonSource::new(file1));
+    let source2 = Box::new(NdjsonSource::new(file2));
+    let (join_key1, join_key2) = parse_keys(key1, key2)?;
+    let members = (join_key1.members(), join_key2.members());
+    let mut sink = NdjsonSink::with_opts(fout, &opts, members)?;
+    let mut stats = join_into(source1, key1, source2, key2, &mut sink, opts)?;
+    stats.merge_conflicts = sink.merge_conflicts();
 
-    writeln!(writer, "{}", line)
+    if let Some(n) = sink.grouped_rows() {
+        stats.output_rows = n;
+    }
+
+    Ok(stats)
 }
 
-fn json_get_or_err(json: &Value, key: &str, allow_no_key: bool) -> Result<Value> {
-    if let Some(v) = json.get(key) {
-        return Ok(v.clone());
-    }
+pub(crate) fn join_into<'a, K>(
+    source1: Box<dyn RecordSource + 'a>,
+    key1: &str,
+    source2: Box<dyn RecordSource + 'a>,
+    key2: &str,
+    sink: &mut K,
+    opts: Opts,
+) -> Result<Stats>
+where
+    K: RecordSink + ?Sized,
+{
+    let parse_values = sink.needs_values();
+    let mut rows = rows_from(source1, key1, source2, key2, opts, parse_values)?;
 
-    if allow_no_key {
-        return Ok(json!(null));
+    while let Some(row) = rows.next_row()? {
+        let left = row.left.as_ref().map(|rec| &rec.record);
+        let right = row.right.as_ref().map(|rec| &rec.record);
+        sink.write(row.key(), left, right)?;
     }
 
-    Err(anyhow!("Key '{}' does not exist: {}", key, json))
+    sink.finish()?;
+    Ok(rows.stats())
 }
@@ -0,0 +1,196 @@
+use serde_json::Map;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+// Order of JSON types when the keys have different types
+fn type_rank(v: &Value) -> u8 {
+    match v {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+// Exact decimal value of a number: `0.DIGITS * 10^exp`, where the digits are the two parts
+// with no leading or trailing zeros (zero has no digits)
+struct Decimal<'a> {
+    negative: bool,
+    digits: (&'a str, &'a str),
+    exp: i64,
+}
+
+impl<'a> Decimal<'a> {
+    fn parse(n: &'a serde_json::Number) -> Self {
+        // With arbitrary precision the number keeps the text of the input, e.g. `-12.50e+3`
+        let s = n.as_str();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (mantissa, exp) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], parse_exponent(&s[i + 1..])),
+            None => (s, 0),
+        };
+        let (int, frac) = match mantissa.find('.') {
+            Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+            None => (mantissa, ""),
+        };
+
+        let int = int.trim_start_matches('0');
+        let mut exp = exp.saturating_add(int.len() as i64);
+
+        let digits = if int.is_empty() {
+            let trimmed = frac.trim_start_matches('0');
+            exp = exp.saturating_sub((frac.len() - trimmed.len()) as i64);
+            (trimmed.trim_end_matches('0'), "")
+        } else {
+            match frac.trim_end_matches('0') {
+                "" => (int.trim_end_matches('0'), ""),
+                frac => (int, frac),
+            }
+        };
+
+        if digits.0.is_empty() {
+            return Decimal {
+                negative: false,
+                digits,
+                exp: 0,
+            };
+        }
+
+        Decimal {
+            negative,
+            digits,
+            exp,
+        }
+    }
+
+    fn digits(&self) -> impl Iterator<Item = u8> + 'a {
+        self.digits.0.bytes().chain(self.digits.1.bytes())
+    }
+
+    fn sign(&self) -> i8 {
+        match (self.digits.0.is_empty(), self.negative) {
+            (true, _) => 0,
+            (false, true) => -1,
+            (false, false) => 1,
+        }
+    }
+}
+
+// The exponent saturates, there's no way to tell apart numbers beyond 10^i64::MAX
+fn parse_exponent(s: &str) -> i64 {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    let mut exp: i64 = 0;
+
+    for d in s.bytes() {
+        exp = exp.saturating_mul(10).saturating_add(i64::from(d - b'0'));
+    }
+
+    if negative {
+        -exp
+    } else {
+        exp
+    }
+}
+
+fn compare_numbers(a: &serde_json::Number, b: &serde_json::Number) -> Ordering {
+    let a = Decimal::parse(a);
+    let b = Decimal::parse(b);
+
+    let ord = a.sign().cmp(&b.sign());
+
+    if ord != Ordering::Equal || a.sign() == 0 {
+        return ord;
+    }
+
+    // Magnitudes: the number of integer digits first, then the digits
+    let ord = a.exp.cmp(&b.exp).then_with(|| a.digits().cmp(b.digits()));
+
+    if a.negative {
+        ord.reverse()
+    } else {
+        ord
+    }
+}
+
+// Members of the object sorted by name (the objects keep the order of the input)
+fn sorted_members(m: &Map<String, Value>) -> Vec<(&String, &Value)> {
+    let mut members: Vec<_> = m.iter().collect();
+    members.sort_by_key(|(k, _)| *k);
+    members
+}
+
+// Total order of join keys:
+// null < boolean < number < string < array < object.
+// Numbers are compared numerically, strings lexically (by bytes),
+// arrays and objects element by element.
+pub(super) fn compare_keys(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => {
+            for (x, y) in a.iter().zip(b.iter()) {
+                let ord = compare_keys(x, y);
+
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+
+            a.len().cmp(&b.len())
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            for ((kx, x), (ky, y)) in sorted_members(a).into_iter().zip(sorted_members(b)) {
+                let ord = kx.cmp(ky).then_with(|| compare_keys(x, y));
+
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+
+            a.len().cmp(&b.len())
+        }
+        (a, b) => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+// Numbers that are equal have the same digits and exponent (e.g. `10.0`, `10` and `1e1`)
+fn canonical_number(n: &serde_json::Number) -> String {
+    let d = Decimal::parse(n);
+
+    if d.sign() == 0 {
+        return "0".to_string();
+    }
+
+    let sign = if d.negative { "-" } else { "" };
+    format!("{}0.{}{}e{}", sign, d.digits.0, d.digits.1, d.exp)
+}
+
+// String representation of a key that is the same for the keys that `compare_keys` considers equal
+pub(super) fn canonical_key(v: &Value) -> String {
+    match v {
+        Value::Number(n) => canonical_number(n),
+        Value::Array(a) => {
+            let items: Vec<String> = a.iter().map(canonical_key).collect();
+            format!("[{}]", items.join(","))
+        }
+        Value::Object(m) => {
+            let members: Vec<String> = sorted_members(m)
+                .into_iter()
+                .map(|(k, v)| format!("{}:{}", Value::String(k.clone()), canonical_key(v)))
+                .collect();
+            format!("{{{}}}", members.join(","))
+        }
+        _ => v.to_string(),
+    }
+}
@@ -0,0 +1,277 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// Path to the join member.
+//
+// * `id`, `user.id`, `meta.ids[0]`: Dotted path (`\` escapes `.`, `[`, `]` and `\`)
+// * `/user/id`, `/meta/ids/0`: JSON Pointer (RFC 6901)
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct KeyPath {
+    expr: String,
+    segments: Vec<Segment>,
+}
+
+impl KeyPath {
+    pub fn parse(expr: &str) -> Result<KeyPath> {
+        let segments = if expr.starts_with('/') {
+            parse_pointer(expr)?
+        } else {
+            parse_dotted(expr)?
+        };
+
+        Ok(KeyPath {
+            expr: expr.to_string(),
+            segments,
+        })
+    }
+
+    // Name of the top-level member on the path
+    pub fn member(&self) -> Option<&str> {
+        match self.segments.first() {
+            Some(Segment::Key(k)) => Some(k),
+            _ => None,
+        }
+    }
+
+    // Whether the path is the names of the members (the indexes of arrays do not match)
+    pub fn matches(&self, names: &[&str]) -> bool {
+        self.segments.len() == names.len()
+            && self
+                .segments
+                .iter()
+                .zip(names)
+                .all(|(seg, name)| matches!(seg, Segment::Key(k) if k == name))
+    }
+
+    pub fn get<'a>(&self, json: &'a Value) -> Option<&'a Value> {
+        let mut v = json;
+
+        for seg in &self.segments {
+            v = match (seg, v) {
+                (Segment::Key(k), Value::Object(m)) => m.get(k)?,
+                (Segment::Key(k), Value::Array(a)) => a.get(k.parse::<usize>().ok()?)?,
+                (Segment::Index(i), Value::Array(a)) => a.get(*i)?,
+                _ => return None,
+            };
+        }
+
+        Some(v)
+    }
+
+    // Same as `get` but only the members on the path are parsed
+    pub fn get_raw(&self, json: &RawValue) -> serde_json::Result<Option<Value>> {
+        let mut v = json;
+
+        for seg in &self.segments {
+            v = match (seg, v.get().as_bytes().first()) {
+                (Segment::Key(k), Some(b'{')) => {
+                    let m: HashMap<Cow<str>, &RawValue> = serde_json::from_str(v.get())?;
+
+                    match m.get(k.as_str()) {
+                        Some(v) => v,
+                        None => return Ok(None),
+                    }
+                }
+                (Segment::Key(k), Some(b'[')) => match k.parse::<usize>() {
+                    Ok(i) => match nth_raw(v, i)? {
+                        Some(v) => v,
+                        None => return Ok(None),
+                    },
+                    Err(_) => return Ok(None),
+                },
+                (Segment::Index(i), Some(b'[')) => match nth_raw(v, *i)? {
+                    Some(v) => v,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            };
+        }
+
+        Ok(Some(serde_json::from_str(v.get())?))
+    }
+}
+
+fn nth_raw(array: &RawValue, i: usize) -> serde_json::Result<Option<&RawValue>> {
+    let a: Vec<&RawValue> = serde_json::from_str(array.get())?;
+    Ok(a.get(i).copied())
+}
+
+// Comma-separated list of key paths (`\,` escapes `,`).
+// The key value of a composite key is the array of the component values.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct JoinKey {
+    paths: Vec<KeyPath>,
+}
+
+impl JoinKey {
+    pub fn parse(expr: &str) -> Result<JoinKey> {
+        let paths = split_list(expr)
+            .iter()
+            .map(|e| KeyPath::parse(e))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(JoinKey { paths })
+    }
+
+    pub fn paths(&self) -> &[KeyPath] {
+        &self.paths
+    }
+
+    pub fn is_composite(&self) -> bool {
+        self.paths.len() > 1
+    }
+
+    // Names of the top-level members on the paths
+    pub fn members(&self) -> Vec<String> {
+        self.paths
+            .iter()
+            .filter_map(|p| p.member())
+            .map(|m| m.to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for JoinKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let exprs: Vec<String> = self.paths.iter().map(|p| p.to_string()).collect();
+        write!(f, "{}", exprs.join(","))
+    }
+}
+
+fn split_list(expr: &str) -> Vec<String> {
+    let mut list = vec![];
+    let mut item = String::new();
+    let mut chars = expr.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(',') if item.starts_with('/') => item.push(','),
+                Some(c) => {
+                    item.push('\\');
+                    item.push(c);
+                }
+                None => item.push('\\'),
+            },
+            ',' => list.push(item.split_off(0)),
+            _ => item.push(c),
+        }
+    }
+
+    list.push(item);
+    list
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+fn parse_pointer(expr: &str) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+
+    for token in expr[1..].split('/') {
+        if token.contains('~') && !valid_pointer_escapes(token) {
+            return Err(anyhow!("Invalid JSON Pointer: {}", expr));
+        }
+
+        let key = token.replace("~1", "/").replace("~0", "~");
+        segments.push(Segment::Key(key));
+    }
+
+    Ok(segments)
+}
+
+fn valid_pointer_escapes(token: &str) -> bool {
+    let mut chars = token.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '~' && !matches!(chars.next(), Some('0') | Some('1')) {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn parse_dotted(expr: &str) -> Result<Vec<Segment>> {
+    let invalid = || anyhow!("Invalid key path: {}", expr);
+    let mut segments = vec![];
+    let mut key = String::new();
+    // Whether the current segment is terminated by an index (e.g. `ids[0]`)
+    let mut indexed = false;
+    let mut chars = expr.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if indexed {
+                    return Err(invalid());
+                }
+
+                key.push(chars.next().ok_or_else(invalid)?);
+            }
+            '.' => {
+                if !indexed {
+                    if key.is_empty() {
+                        return Err(invalid());
+                    }
+
+                    segments.push(Segment::Key(key.split_off(0)));
+                }
+
+                indexed = false;
+            }
+            '[' => {
+                if !key.is_empty() {
+                    segments.push(Segment::Key(key.split_off(0)));
+                } else if !indexed && !segments.is_empty() {
+                    return Err(invalid());
+                }
+
+                let mut digits = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(d) if d.is_ascii_digit() => digits.push(d),
+                        _ => return Err(invalid()),
+                    }
+                }
+
+                segments.push(Segment::Index(digits.parse().map_err(|_| invalid())?));
+                indexed = true;
+            }
+            ']' => return Err(invalid()),
+            _ => {
+                if indexed {
+                    return Err(invalid());
+                }
+
+                key.push(c);
+            }
+        }
+    }
+
+    if !indexed {
+        if key.is_empty() {
+            return Err(invalid());
+        }
+
+        segments.push(Segment::Key(key));
+    }
+
+    Ok(segments)
+}
@@ -0,0 +1,193 @@
+use super::path::JoinKey;
+use super::path::KeyPath;
+use super::reject::ErrorHandler;
+use super::source::parse_line;
+use super::source::MalformedRecord;
+use super::source::Record;
+use super::source::RecordSource;
+use anyhow::anyhow;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::ops::Deref;
+use std::rc::Rc;
+
+// Maximum length of the JSON echoed in error messages
+const MAX_ECHO_LEN: usize = 200;
+
+// Record with the value of the join key
+pub(super) struct Keyed {
+    pub record: Record,
+    pub key: Value,
+}
+
+impl Deref for Keyed {
+    type Target = Record;
+
+    fn deref(&self) -> &Record {
+        &self.record
+    }
+}
+
+// Input of the records with the join key
+pub(super) trait Source {
+    fn read(&mut self) -> Result<Option<Keyed>>;
+
+    // Number of the records read including the malformed records
+    fn lines(&self) -> u64;
+}
+
+pub(super) struct Reader<'a> {
+    inner: Box<dyn RecordSource + 'a>,
+    name: String,
+    key: JoinKey,
+    allow_no_key: bool,
+    parse_values: bool,
+    errors: Rc<ErrorHandler>,
+    lines: u64,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(
+        inner: Box<dyn RecordSource + 'a>,
+        name: &str,
+        key: &JoinKey,
+        allow_no_key: bool,
+        parse_values: bool,
+        errors: &Rc<ErrorHandler>,
+    ) -> Self {
+        Reader {
+            inner,
+            name: name.to_string(),
+            key: key.clone(),
+            allow_no_key,
+            parse_values,
+            errors: Rc::clone(errors),
+            lines: 0,
+        }
+    }
+}
+
+impl<'a> Source for Reader<'a> {
+    fn read(&mut self) -> Result<Option<Keyed>> {
+        loop {
+            // Malformed records are skipped unless the handler aborts the join
+            let mut record = match self.inner.read() {
+                Ok(Some(record)) => record,
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    let m = e.downcast::<MalformedRecord>()?;
+                    self.lines += 1;
+                    self.errors
+                        .handle(&self.name, m.line, m.offset, &m.raw, m.error)?;
+                    continue;
+                }
+            };
+
+            self.lines += 1;
+
+            match extract_key(&mut record, &self.key, self.allow_no_key, self.parse_values) {
+                Ok(key) => return Ok(Some(Keyed { record, key })),
+                Err(e) => {
+                    self.errors
+                        .handle(&self.name, record.line, record.offset, &record.raw, e)?;
+                }
+            }
+        }
+    }
+
+    fn lines(&self) -> u64 {
+        self.lines
+    }
+}
+
+// Key of the record. The record is parsed unless `parse_values` is false,
+// in which case only the members on the key path are parsed.
+pub(super) fn extract_key(
+    record: &mut Record,
+    key: &JoinKey,
+    allow_no_key: bool,
+    parse_values: bool,
+) -> Result<Value> {
+    if record.json.is_none() && parse_values {
+        record.json = Some(parse_line(&record.raw)?);
+    }
+
+    match record.json {
+        Some(ref json) => get_key(json, key, allow_no_key),
+        None => get_raw_key(&record.raw, key, allow_no_key),
+    }
+}
+
+fn get_key(json: &Value, key: &JoinKey, allow_no_key: bool) -> Result<Value> {
+    let paths = key.paths();
+
+    if !key.is_composite() {
+        return json_get_or_err(json, &paths[0], allow_no_key);
+    }
+
+    let vals = paths
+        .iter()
+        .map(|path| json_get_or_err(json, path, allow_no_key))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Value::Array(vals))
+}
+
+fn get_raw_key(raw: &str, key: &JoinKey, allow_no_key: bool) -> Result<Value> {
+    let json = match serde_json::from_str::<&RawValue>(raw) {
+        Ok(json) if json.get().starts_with('{') => json,
+        // Report the same error as parsing the whole line
+        _ => {
+            return Err(parse_line(raw)
+                .err()
+                .unwrap_or_else(|| anyhow!("Failed to parse JSON: {}", truncate(raw.trim_end()))))
+        }
+    };
+
+    let get = |path: &KeyPath| match path.get_raw(json) {
+        Ok(Some(v)) => Ok(v),
+        Ok(None) if allow_no_key => Ok(json!(null)),
+        Ok(None) => Err(anyhow!(
+            "Key '{}' does not exist: {}",
+            path,
+            truncate(json.get())
+        )),
+        Err(e) => Err(anyhow!(
+            "Failed to parse JSON: {}: {}",
+            e,
+            truncate(json.get())
+        )),
+    };
+
+    if !key.is_composite() {
+        return get(&key.paths()[0]);
+    }
+
+    let vals = key.paths().iter().map(get).collect::<Result<Vec<_>>>()?;
+    Ok(Value::Array(vals))
+}
+
+fn json_get_or_err(json: &Value, key: &KeyPath, allow_no_key: bool) -> Result<Value> {
+    if let Some(v) = key.get(json) {
+        return Ok(v.clone());
+    }
+
+    if allow_no_key {
+        return Ok(json!(null));
+    }
+
+    Err(anyhow!(
+        "Key '{}' does not exist: {}",
+        key,
+        truncate(&json.to_string())
+    ))
+}
+
+pub(super) fn truncate(s: &str) -> String {
+    match s.char_indices().nth(MAX_ECHO_LEN) {
+        Some((i, _)) => format!("{}...", &s[..i]),
+        None => s.to_string(),
+    }
+}
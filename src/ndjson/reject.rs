@@ -0,0 +1,90 @@
+use super::OnError;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::json;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::io::Write;
+
+pub(super) struct ErrorHandler {
+    on_error: OnError,
+    max_errors: Option<u64>,
+    count: Cell<u64>,
+    quarantine: RefCell<Option<io::BufWriter<fs::File>>>,
+}
+
+impl ErrorHandler {
+    pub fn new(on_error: &OnError, max_errors: Option<u64>) -> Result<Self> {
+        let quarantine = match on_error {
+            OnError::Quarantine(path) => {
+                let f = fs::File::create(path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?;
+                Some(io::BufWriter::new(f))
+            }
+            _ => None,
+        };
+
+        Ok(ErrorHandler {
+            on_error: on_error.clone(),
+            max_errors,
+            count: Cell::new(0),
+            quarantine: RefCell::new(quarantine),
+        })
+    }
+
+    // Return Err if the join should be aborted
+    pub fn handle(
+        &self,
+        name: &str,
+        line: u64,
+        offset: u64,
+        raw: &str,
+        err: anyhow::Error,
+    ) -> Result<()> {
+        let msg = format!("{}:{}:{}: {:#}", name, line, offset, err);
+
+        if self.on_error == OnError::Fail {
+            return Err(anyhow!(msg));
+        }
+
+        self.count.set(self.count.get() + 1);
+
+        if let Some(max) = self.max_errors {
+            if self.count.get() > max {
+                return Err(anyhow!("Too many malformed lines (> {}): {}", max, msg));
+            }
+        }
+
+        if let Some(ref mut writer) = *self.quarantine.borrow_mut() {
+            let rejected = json!({
+                "file": name,
+                "line": line,
+                "offset": offset,
+                "error": format!("{:#}", err),
+                "raw": raw.trim_end_matches(&['\r', '\n'][..]),
+            });
+
+            writeln!(writer, "{}", rejected)?;
+        } else {
+            eprintln!("jljoin: warning: {}", msg);
+        }
+
+        Ok(())
+    }
+
+    // Number of the malformed lines that were skipped
+    pub fn count(&self) -> u64 {
+        self.count.get()
+    }
+
+    pub fn finish(&self) -> Result<()> {
+        if let Some(ref mut writer) = *self.quarantine.borrow_mut() {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use super::reader::Keyed;
+use super::reject::ErrorHandler;
+use super::stats::Stats;
+use anyhow::Result;
+use serde_json::Value;
+use std::rc::Rc;
+use std::time::Instant;
+
+// Output row of the join.
+// Either side is `None` for an unpaired line, or for a line of `--semi` and `--only-unmatched`.
+pub(super) struct Row {
+    pub left: Option<Rc<Keyed>>,
+    pub right: Option<Rc<Keyed>>,
+}
+
+impl Row {
+    // Value of the join key of either side
+    pub fn key(&self) -> &Value {
+        match (&self.left, &self.right) {
+            (Some(rec), _) | (None, Some(rec)) => &rec.key,
+            (None, None) => &Value::Null,
+        }
+    }
+}
+
+// Join algorithm yielding the rows one by one
+pub(super) trait Engine {
+    fn next_row(&mut self) -> Result<Option<Row>>;
+
+    // Statistics of the inputs and the matched keys so far
+    fn stats(&self) -> Stats;
+}
+
+/// Position of a line in the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    /// Line number (1-origin)
+    pub line: u64,
+    /// Byte offset of the start of the line
+    pub offset: u64,
+}
+
+/// Row of the join result.
+///
+/// `left` is the record of FILE1 and `right` is the record of FILE2.
+/// Either side is `None` for an unpaired record (outer join and anti-join)
+/// or for a record of the semi-join.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinedRow {
+    /// Value of the join key (an array for a composite key)
+    pub key: Value,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+    pub left_pos: Option<Position>,
+    pub right_pos: Option<Position>,
+}
+
+fn into_value(rec: Option<Rc<Keyed>>) -> Result<(Option<Value>, Option<Position>)> {
+    match rec {
+        Some(rec) => {
+            let pos = Position {
+                line: rec.line,
+                offset: rec.offset,
+            };
+
+            let json = match Rc::try_unwrap(rec) {
+                Ok(rec) => rec.record.into_value()?,
+                Err(rec) => rec.value()?.into_owned(),
+            };
+
+            Ok((Some(json), Some(pos)))
+        }
+        None => Ok((None, None)),
+    }
+}
+
+/// Iterator over the rows of a join.
+///
+/// The inputs are read lazily as the rows are consumed.
+/// The iteration ends after the first error.
+pub struct Rows<'a> {
+    engine: Box<dyn Engine + 'a>,
+    errors: Rc<ErrorHandler>,
+    started: Instant,
+    output_rows: u64,
+    done: bool,
+}
+
+impl<'a> Rows<'a> {
+    pub(super) fn new(engine: Box<dyn Engine + 'a>, errors: Rc<ErrorHandler>) -> Self {
+        Rows {
+            engine,
+            errors,
+            started: Instant::now(),
+            output_rows: 0,
+            done: false,
+        }
+    }
+
+    pub(super) fn next_row(&mut self) -> Result<Option<Row>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let row = self.engine.next_row();
+
+        match row {
+            Ok(Some(_)) => self.output_rows += 1,
+            Ok(None) => {
+                self.done = true;
+                self.errors.finish()?;
+            }
+            Err(_) => self.done = true,
+        }
+
+        row
+    }
+
+    /// Statistics of the join so far.
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.engine.stats();
+        stats.output_rows = self.output_rows;
+        stats.malformed_lines = self.errors.count();
+        stats.elapsed = self.started.elapsed();
+        stats
+    }
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = Result<JoinedRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.next_row() {
+            Ok(row) => row?,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(into_joined(row))
+    }
+}
+
+fn into_joined(row: Row) -> Result<JoinedRow> {
+    let key = row.key().clone();
+    let (left, left_pos) = into_value(row.left)?;
+    let (right, right_pos) = into_value(row.right)?;
+
+    Ok(JoinedRow {
+        key,
+        left,
+        right,
+        left_pos,
+        right_pos,
+    })
+}
@@ -0,0 +1,246 @@
+use super::check_opts;
+use super::group::Grouper;
+use super::merger::Merger;
+use super::path::JoinKey;
+use super::source::Record;
+use super::Opts;
+use super::OutputShape;
+use crate::JoinOptions;
+use anyhow::Result;
+use serde_json::Value;
+use std::io;
+use std::io::Write;
+
+/// Output of the rows of the join.
+pub trait RecordSink {
+    /// Write a row of the join.
+    ///
+    /// `left` is the record of FILE1 and `right` is the record of FILE2.
+    /// Either side is `None` for an unpaired record (outer join and anti-join)
+    /// or for a record of the semi-join.
+    fn write(&mut self, key: &Value, left: Option<&Record>, right: Option<&Record>) -> Result<()>;
+
+    /// Whether the records need to be parsed. If false, only the keys are parsed
+    /// from the records of NDJSON and `Record::json` may be `None`.
+    fn needs_values(&self) -> bool {
+        true
+    }
+
+    /// Flush the output after the last row.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink writing the rows as NDJSON (`[json1,json2]`, `{"key":KEY,"left":json1,"right":json2}`, the merged JSON or the grouped JSON).
+pub struct NdjsonSink<W: io::Write> {
+    writer: io::BufWriter<W>,
+    opts: Opts,
+    merger: Option<Merger>,
+    grouper: Option<Grouper>,
+}
+
+impl<W> NdjsonSink<W>
+where
+    W: io::Write,
+{
+    /// Sink formatting the rows by `--merge`, `--group`, `--output-shape`, `--placeholder` and the type of the join of `options`.
+    ///
+    /// All the members are prefixed or nested by the options of merging.
+    /// Use [`NdjsonSink::with_keys`] to leave the members of the join keys as they are.
+    ///
+    /// Fails if the file of [`OnConflict::Report`](crate::OnConflict::Report) cannot be created
+    /// or the options fail [`JoinOptions::check`].
+    pub fn new(out: W, options: &JoinOptions) -> Result<Self> {
+        Self::with_opts(out, options.opts(), (vec![], vec![]))
+    }
+
+    /// Same as [`NdjsonSink::new`] but the members of the join keys of FILE1 and FILE2
+    /// are not prefixed or nested.
+    pub fn with_keys(out: W, options: &JoinOptions, key1: &str, key2: &str) -> Result<Self> {
+        let members = (
+            JoinKey::parse(key1)?.members(),
+            JoinKey::parse(key2)?.members(),
+        );
+
+        Self::with_opts(out, options.opts(), members)
+    }
+
+    pub(super) fn with_opts(
+        out: W,
+        opts: &Opts,
+        key_members: (Vec<String>, Vec<String>),
+    ) -> Result<Self> {
+        check_opts(opts)?;
+
+        let merger = match opts.merge {
+            Some(n) => Some(Merger::new(n, opts, key_members)?),
+            None => None,
+        };
+
+        let grouper = match opts.group {
+            Some((n, ref name)) => Some(Grouper::new(n, name)),
+            None => None,
+        };
+
+        Ok(NdjsonSink {
+            writer: io::BufWriter::new(out),
+            opts: opts.clone(),
+            merger,
+            grouper,
+        })
+    }
+
+    // Number of the grouped lines written, which differs from the number of the rows of the join
+    pub(super) fn grouped_rows(&self) -> Option<u64> {
+        self.grouper.as_ref().map(|g| g.output_rows())
+    }
+
+    /// Number of the members of the merged JSON with different values.
+    pub fn merge_conflicts(&self) -> u64 {
+        self.merger.as_ref().map_or(0, |m| m.conflicts())
+    }
+}
+
+impl<W> RecordSink for NdjsonSink<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, key: &Value, left: Option<&Record>, right: Option<&Record>) -> Result<()> {
+        let writer = &mut self.writer;
+        let opts = &self.opts;
+
+        if let Some(ref mut grouper) = self.grouper {
+            return grouper.write(writer, key, left, right);
+        }
+
+        if let Some(ref mut merger) = self.merger {
+            return print_merged(writer, key, left, right, merger);
+        }
+
+        match (left, right) {
+            (Some(rec1), Some(rec2)) => print_pair(writer, key, rec1, rec2, opts),
+            (Some(rec1), None) => print_single(writer, key, rec1, 1, opts),
+            (None, Some(rec2)) => print_single(writer, key, rec2, 2, opts),
+            (None, None) => Ok(()),
+        }
+    }
+
+    // The records are written as is unless merging them
+    fn needs_values(&self) -> bool {
+        self.opts.merge.is_some() || self.grouper.is_some()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(ref mut grouper) = self.grouper {
+            grouper.flush(&mut self.writer)?;
+        }
+
+        if let Some(ref mut merger) = self.merger {
+            merger.finish()?;
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// JSON text of the record without the surrounding whitespace and the newline
+fn raw_json(rec: &Record) -> &str {
+    rec.raw.trim_matches(&[' ', '\t', '\r', '\n'][..])
+}
+
+// Print `{"key":KEY,"left":json1,"right":json2}`
+fn print_object<T>(writer: &mut T, key: &Value, json1: &str, json2: &str, opts: &Opts) -> Result<()>
+where
+    T: io::Write,
+{
+    let (name1, name2) = &opts.names;
+
+    writeln!(
+        writer,
+        r#"{{"key":{},{}:{},{}:{}}}"#,
+        key,
+        Value::String(name1.clone()),
+        json1,
+        Value::String(name2.clone()),
+        json2
+    )?;
+
+    Ok(())
+}
+
+// Print the merged JSON, or the unpaired line of `--outer` formatted in the same way
+fn print_merged<T>(
+    writer: &mut T,
+    key: &Value,
+    left: Option<&Record>,
+    right: Option<&Record>,
+    merger: &mut Merger,
+) -> Result<()>
+where
+    T: io::Write,
+{
+    let out_json = match (left, right) {
+        (Some(rec1), Some(rec2)) => merger.merge(key, rec1, rec2)?,
+        (Some(rec1), None) => merger.shape(rec1.value()?.into_owned(), 1),
+        (None, Some(rec2)) => merger.shape(rec2.value()?.into_owned(), 2),
+        (None, None) => return Ok(()),
+    };
+
+    writeln!(writer, "{}", out_json)?;
+    Ok(())
+}
+
+fn print_pair<T>(
+    writer: &mut T,
+    key: &Value,
+    rec1: &Record,
+    rec2: &Record,
+    opts: &Opts,
+) -> Result<()>
+where
+    T: io::Write,
+{
+    if opts.output_shape == OutputShape::Object {
+        print_object(writer, key, raw_json(rec1), raw_json(rec2), opts)?;
+    } else {
+        writeln!(writer, "[{},{}]", raw_json(rec1), raw_json(rec2))?;
+    }
+
+    Ok(())
+}
+
+// Print the line of `--semi`, `--only-unmatched` or the unpaired line of `--outer`
+fn print_single<T>(
+    writer: &mut T,
+    key: &Value,
+    rec: &Record,
+    filenum: u8,
+    opts: &Opts,
+) -> Result<()>
+where
+    T: io::Write,
+{
+    let json = raw_json(rec);
+
+    if !opts.emits_pairs() {
+        writeln!(writer, "{}", json)?;
+    } else if opts.output_shape == OutputShape::Object {
+        let placeholder = opts.placeholder.to_string();
+
+        let (json1, json2) = if filenum == 1 {
+            (json, &*placeholder)
+        } else {
+            (&*placeholder, json)
+        };
+
+        print_object(writer, key, json1, json2, opts)?;
+    } else if filenum == 1 {
+        writeln!(writer, "[{},{}]", json, opts.placeholder)?;
+    } else {
+        writeln!(writer, "[{},{}]", opts.placeholder, json)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use super::block::read_positioned;
+use super::block::write_positioned;
+use super::ord::compare_keys;
+use super::path::JoinKey;
+use super::reader::extract_key;
+use super::reader::Keyed;
+use super::reader::Source;
+use super::source::Record;
+use anyhow::Result;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io;
+use std::io::Seek;
+use std::mem;
+
+// Head line of a sorted chunk
+struct Head {
+    rec: Keyed,
+    chunk: usize,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    // Reversed for the min-heap. The earlier chunk comes first to keep the sort stable.
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&other.rec.key, &self.rec.key).then_with(|| other.chunk.cmp(&self.chunk))
+    }
+}
+
+// Number of the chunks merged at once, which bounds the open temporary files
+const MERGE_FAN_IN: usize = 64;
+
+// Sorted chunk with its level: a chunk of level N+1 is merged from MERGE_FAN_IN chunks of level N
+type Chunk = (u32, io::BufReader<fs::File>);
+
+// Approximate memory size of a value
+fn value_size(v: &Value) -> usize {
+    let children = match v {
+        Value::Number(n) => n.as_str().len(),
+        Value::String(s) => s.len(),
+        Value::Array(a) => a.iter().map(value_size).sum(),
+        Value::Object(m) => m.iter().map(|(k, v)| k.len() + value_size(v)).sum(),
+        _ => 0,
+    };
+
+    mem::size_of::<Value>() + children
+}
+
+// Approximate memory size of a buffered record: the line, the key and the parsed record if any
+fn record_size(rec: &Keyed) -> usize {
+    let json_size = rec.json.as_ref().map_or(0, value_size);
+    mem::size_of::<Keyed>() + rec.raw.len() + value_size(&rec.key) + json_size
+}
+
+fn rewind(writer: io::BufWriter<fs::File>) -> Result<io::BufReader<fs::File>> {
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.seek(io::SeekFrom::Start(0))?;
+    Ok(io::BufReader::new(file))
+}
+
+fn write_chunk(recs: &mut Vec<Keyed>) -> Result<io::BufReader<fs::File>> {
+    recs.sort_by(|a, b| compare_keys(&a.key, &b.key));
+    let mut writer = io::BufWriter::new(tempfile::tempfile()?);
+
+    for rec in recs.drain(..) {
+        write_positioned(&mut writer, rec.line, rec.offset, &rec.raw)?;
+    }
+
+    rewind(writer)
+}
+
+// K-way merge of the sorted chunks
+struct ChunkMerge {
+    key: JoinKey,
+    allow_no_key: bool,
+    parse_values: bool,
+    chunks: Vec<io::BufReader<fs::File>>,
+    heap: BinaryHeap<Head>,
+}
+
+impl ChunkMerge {
+    fn new(
+        chunks: Vec<io::BufReader<fs::File>>,
+        key: &JoinKey,
+        allow_no_key: bool,
+        parse_values: bool,
+    ) -> Result<Self> {
+        let mut merge = ChunkMerge {
+            key: key.clone(),
+            allow_no_key,
+            parse_values,
+            chunks,
+            heap: BinaryHeap::new(),
+        };
+
+        for i in 0..merge.chunks.len() {
+            merge.push_head(i)?;
+        }
+
+        Ok(merge)
+    }
+
+    fn push_head(&mut self, chunk: usize) -> Result<()> {
+        // The lines in the chunks have been validated already
+        if let Some((line, offset, raw)) = read_positioned(&mut self.chunks[chunk])? {
+            let mut record = Record {
+                raw,
+                json: None,
+                line,
+                offset,
+            };
+
+            let key = extract_key(&mut record, &self.key, self.allow_no_key, self.parse_values)?;
+
+            self.heap.push(Head {
+                rec: Keyed { record, key },
+                chunk,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Keyed>> {
+        match self.heap.pop() {
+            Some(head) => {
+                self.push_head(head.chunk)?;
+                Ok(Some(head.rec))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+// Merge the last `n` chunks into a chunk of the next level.
+// The chunks are in the order of the input, so the merged chunk keeps the sort stable.
+fn merge_last_chunks(
+    chunks: &mut Vec<Chunk>,
+    n: usize,
+    key: &JoinKey,
+    allow_no_key: bool,
+) -> Result<()> {
+    let merged: Vec<Chunk> = chunks.drain(chunks.len() - n..).collect();
+    let level = merged.iter().map(|(level, _)| *level).max().unwrap_or(0) + 1;
+    let readers = merged.into_iter().map(|(_, reader)| reader).collect();
+    let mut merge = ChunkMerge::new(readers, key, allow_no_key, false)?;
+    let mut writer = io::BufWriter::new(tempfile::tempfile()?);
+
+    while let Some(rec) = merge.next()? {
+        write_positioned(&mut writer, rec.line, rec.offset, &rec.raw)?;
+    }
+
+    chunks.push((level, rewind(writer)?));
+    Ok(())
+}
+
+// Add a chunk of level 0, merging the chunks of the same level when there are MERGE_FAN_IN of them.
+// The levels decrease along the chunks, so less than MERGE_FAN_IN chunks of each level are open.
+fn push_chunk(
+    chunks: &mut Vec<Chunk>,
+    chunk: io::BufReader<fs::File>,
+    key: &JoinKey,
+    allow_no_key: bool,
+) -> Result<()> {
+    chunks.push((0, chunk));
+
+    while chunks.len() >= MERGE_FAN_IN {
+        let last = &chunks[chunks.len() - MERGE_FAN_IN..];
+
+        if last.iter().any(|(level, _)| *level != last[0].0) {
+            break;
+        }
+
+        merge_last_chunks(chunks, MERGE_FAN_IN, key, allow_no_key)?;
+    }
+
+    Ok(())
+}
+
+// Records sorted by the join key with the external merge sort.
+// The lines are sorted in chunks of `memory_limit` bytes, the chunks are merged MERGE_FAN_IN at a time
+// into fewer chunks, and the last MERGE_FAN_IN chunks at most are merged while reading.
+pub(super) struct Sorted {
+    lines: u64,
+    merge: ChunkMerge,
+}
+
+impl Sorted {
+    pub fn new(
+        mut source: Box<dyn Source + '_>,
+        key: &JoinKey,
+        allow_no_key: bool,
+        parse_values: bool,
+        memory_limit: usize,
+    ) -> Result<Self> {
+        let mut chunks = vec![];
+        let mut recs = vec![];
+        let mut size = 0;
+
+        while let Some(rec) = source.read()? {
+            size += record_size(&rec);
+            recs.push(rec);
+
+            if size >= memory_limit {
+                push_chunk(&mut chunks, write_chunk(&mut recs)?, key, allow_no_key)?;
+                size = 0;
+            }
+        }
+
+        if !recs.is_empty() {
+            push_chunk(&mut chunks, write_chunk(&mut recs)?, key, allow_no_key)?;
+        }
+
+        while chunks.len() > MERGE_FAN_IN {
+            let n = (chunks.len() - MERGE_FAN_IN + 1).min(MERGE_FAN_IN);
+            merge_last_chunks(&mut chunks, n, key, allow_no_key)?;
+        }
+
+        let readers = chunks.into_iter().map(|(_, reader)| reader).collect();
+
+        Ok(Sorted {
+            lines: source.lines(),
+            merge: ChunkMerge::new(readers, key, allow_no_key, parse_values)?,
+        })
+    }
+}
+
+impl Source for Sorted {
+    fn read(&mut self) -> Result<Option<Keyed>> {
+        self.merge.next()
+    }
+
+    fn lines(&self) -> u64 {
+        self.lines
+    }
+}
@@ -0,0 +1,152 @@
+use super::reader::truncate;
+use anyhow::anyhow;
+use anyhow::Result;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+
+/// Record read from an input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// JSON text of the record (the line of NDJSON).
+    /// It is written to the temporary files and the quarantine file.
+    pub raw: String,
+    /// Parsed record (a JSON object), or `None` if it has not been parsed (see [`Record::value`])
+    pub json: Option<Value>,
+    /// Line number (1-origin) of the record in the input
+    pub line: u64,
+    /// Byte offset of the record in the input
+    pub offset: u64,
+}
+
+impl Record {
+    /// Parsed record, parsing `raw` if it has not been parsed.
+    pub fn value(&self) -> Result<Cow<'_, Value>> {
+        match self.json {
+            Some(ref json) => Ok(Cow::Borrowed(json)),
+            None => Ok(Cow::Owned(parse_line(&self.raw)?)),
+        }
+    }
+
+    pub fn into_value(self) -> Result<Value> {
+        match self.json {
+            Some(json) => Ok(json),
+            None => parse_line(&self.raw),
+        }
+    }
+}
+
+/// Error of a record that cannot be read as a JSON object.
+///
+/// The join skips or quarantines the record by [`OnError`](crate::OnError)
+/// when a [`RecordSource`] returns this error.
+#[derive(Debug)]
+pub struct MalformedRecord {
+    pub raw: String,
+    pub line: u64,
+    pub offset: u64,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for MalformedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl error::Error for MalformedRecord {}
+
+/// Input of the records to join.
+pub trait RecordSource {
+    /// Read the next record, or `None` at the end of the input.
+    ///
+    /// A record without `json` is parsed (or only its key is extracted) by the join.
+    /// Return [`MalformedRecord`] for a malformed record; any other error aborts the join.
+    fn read(&mut self) -> Result<Option<Record>>;
+}
+
+/// Source of the lines of NDJSON.
+///
+/// The lines are not parsed here so that the join can parse only the key when the whole record is not needed.
+pub struct NdjsonSource<R> {
+    inner: io::BufReader<R>,
+    line: u64,
+    offset: u64,
+}
+
+impl<R> NdjsonSource<R>
+where
+    R: io::Read,
+{
+    pub fn new(inner: R) -> Self {
+        NdjsonSource {
+            inner: io::BufReader::new(inner),
+            line: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl<R> RecordSource for NdjsonSource<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self) -> Result<Option<Record>> {
+        let mut buf = vec![];
+        let n = self.inner.read_until(b'\n', &mut buf)?;
+
+        if n == 0 {
+            return Ok(None);
+        }
+
+        let offset = self.offset;
+        self.line += 1;
+        self.offset += n as u64;
+
+        let raw = match String::from_utf8(buf) {
+            Ok(raw) => raw,
+            Err(e) => {
+                let raw = String::from_utf8_lossy(e.as_bytes()).into_owned();
+
+                return Err(anyhow::Error::new(MalformedRecord {
+                    error: anyhow!("Invalid UTF-8: {}: {}", e, truncate(raw.trim_end())),
+                    raw,
+                    line: self.line,
+                    offset,
+                }));
+            }
+        };
+
+        Ok(Some(Record {
+            raw,
+            json: None,
+            line: self.line,
+            offset,
+        }))
+    }
+}
+
+pub(super) fn parse_line(line: &str) -> Result<Value> {
+    let r: serde_json::error::Result<Value> = serde_json::from_str(line);
+
+    match r {
+        Err(e) => Err(anyhow!(
+            "Failed to parse JSON: {}: {}",
+            e,
+            truncate(line.trim_end())
+        )),
+        Ok(v) => {
+            if !v.is_object() {
+                Err(anyhow!(
+                    "JSON in row is not Object type: {}",
+                    truncate(&v.to_string())
+                ))
+            } else {
+                Ok(v)
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use serde_json::json;
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+/// Statistics of an input.
+///
+/// The key statistics are `None` if they cannot be counted (the streamed file of the hash join).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FileStats {
+    /// Lines read including the malformed lines
+    pub lines: u64,
+    pub distinct_keys: Option<u64>,
+    /// Distinct keys without a partner
+    pub unmatched_keys: Option<u64>,
+    /// Largest number of the lines with the same key
+    pub largest_block: Option<u64>,
+}
+
+/// Statistics of a join.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub file1: FileStats,
+    pub file2: FileStats,
+    pub matched_keys: u64,
+    pub output_rows: u64,
+    pub malformed_lines: u64,
+    /// Members of the merged JSON with different values (see [`OnConflict`](crate::OnConflict))
+    pub merge_conflicts: u64,
+    pub elapsed: Duration,
+}
+
+impl FileStats {
+    fn to_json(&self) -> Value {
+        json!({
+            "lines": self.lines,
+            "distinct_keys": self.distinct_keys,
+            "unmatched_keys": self.unmatched_keys,
+            "largest_block": self.largest_block,
+        })
+    }
+}
+
+impl Stats {
+    /// Statistics as JSON (`elapsed_sec` is the elapsed time in seconds).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "file1": self.file1.to_json(),
+            "file2": self.file2.to_json(),
+            "matched_keys": self.matched_keys,
+            "output_rows": self.output_rows,
+            "malformed_lines": self.malformed_lines,
+            "merge_conflicts": self.merge_conflicts,
+            "elapsed_sec": self.elapsed.as_secs_f64(),
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opt = |n: Option<u64>| n.map_or("-".to_string(), |n| n.to_string());
+
+        writeln!(f, "lines: {} / {}", self.file1.lines, self.file2.lines)?;
+        writeln!(
+            f,
+            "distinct keys: {} / {}",
+            opt(self.file1.distinct_keys),
+            opt(self.file2.distinct_keys)
+        )?;
+        writeln!(f, "matched keys: {}", self.matched_keys)?;
+        writeln!(
+            f,
+            "unmatched keys: {} / {}",
+            opt(self.file1.unmatched_keys),
+            opt(self.file2.unmatched_keys)
+        )?;
+        writeln!(
+            f,
+            "largest block: {} / {}",
+            opt(self.file1.largest_block),
+            opt(self.file2.largest_block)
+        )?;
+        writeln!(f, "output rows: {}", self.output_rows)?;
+        writeln!(f, "malformed lines: {}", self.malformed_lines)?;
+        writeln!(f, "merge conflicts: {}", self.merge_conflicts)?;
+        write!(f, "elapsed: {:.3}s", self.elapsed.as_secs_f64())
+    }
+}
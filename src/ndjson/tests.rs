@@ -1,9 +1,30 @@
+use super::block::Block;
+use super::check_keys;
+use super::check_opts;
 use super::join;
+use super::join_into;
+use super::reader::Keyed;
+use super::rows;
+use super::JoinedRow;
+use super::MalformedRecord;
+use super::MergeStrategy;
+use super::OnConflict;
+use super::OnError;
 use super::Opts;
+use super::Outer;
+use super::OutputShape;
+use super::Position;
+use super::Record;
+use super::RecordSink;
+use super::RecordSource;
+use super::Stats;
 use indoc::indoc;
+use serde_json::json;
+use serde_json::Value;
 use std::io;
 use std::io::Seek;
 use std::io::Write;
+use std::rc::Rc;
 use std::str;
 
 static NDJSON1: &str = indoc! {r#"
@@ -53,39 +74,28 @@ fn test_join() {
     let mut buf = Vec::new();
     let fout = Box::new(&mut buf);
 
-    join(
-        f1,
-        "id",
-        f2,
-        "id",
-        fout,
-        Opts {
-            allow_no_key: false,
-            merge: None,
-        },
-    )
-    .unwrap();
+    join(f1, "id", f2, "id", fout, Opts::default()).unwrap();
 
     assert_eq!(
         indoc! {r#"
-            [{"file":"ndjson1","id":1,"sub_id":11,"val":"both"},{"file":"ndjson2","id":1,"sub_id":11,"val":"both"}]
-            [{"file":"ndjson1","id":2,"sub_id":21,"val":"both/multi 1"},{"file":"ndjson2","id":2,"sub_id":21,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":2,"sub_id":22,"val":"both/multi 1"},{"file":"ndjson2","id":2,"sub_id":21,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":3,"sub_id":31,"val":"both/multi 2"},{"file":"ndjson2","id":3,"sub_id":31,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":3,"sub_id":31,"val":"both/multi 2"},{"file":"ndjson2","id":3,"sub_id":32,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":6,"sub_id":62,"val":"both/multi"},{"file":"ndjson2","id":6,"sub_id":62,"val":"both/multi"}]
-            [{"file":"ndjson1","id":6,"sub_id":62,"val":"both/multi"},{"file":"ndjson2","id":6,"sub_id":61,"val":"both/multi"}]
-            [{"file":"ndjson1","id":6,"sub_id":61,"val":"both/multi"},{"file":"ndjson2","id":6,"sub_id":62,"val":"both/multi"}]
-            [{"file":"ndjson1","id":6,"sub_id":61,"val":"both/multi"},{"file":"ndjson2","id":6,"sub_id":61,"val":"both/multi"}]
-            [{"file":"ndjson1","id":11,"sub_id":111,"val":"both"},{"file":"ndjson2","id":11,"sub_id":111,"val":"both"}]
-            [{"file":"ndjson1","id":12,"sub_id":121,"val":"both/multi 1"},{"file":"ndjson2","id":12,"sub_id":121,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":12,"sub_id":122,"val":"both/multi 1"},{"file":"ndjson2","id":12,"sub_id":121,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":13,"sub_id":131,"val":"both/multi 2"},{"file":"ndjson2","id":13,"sub_id":131,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":13,"sub_id":131,"val":"both/multi 2"},{"file":"ndjson2","id":13,"sub_id":132,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":16,"sub_id":162,"val":"both/multi"},{"file":"ndjson2","id":16,"sub_id":162,"val":"both/multi"}]
-            [{"file":"ndjson1","id":16,"sub_id":162,"val":"both/multi"},{"file":"ndjson2","id":16,"sub_id":161,"val":"both/multi"}]
-            [{"file":"ndjson1","id":16,"sub_id":161,"val":"both/multi"},{"file":"ndjson2","id":16,"sub_id":162,"val":"both/multi"}]
-            [{"file":"ndjson1","id":16,"sub_id":161,"val":"both/multi"},{"file":"ndjson2","id":16,"sub_id":161,"val":"both/multi"}]
+            [{"id": 1, "sub_id": 11, "file":"ndjson1", "val":"both"},{"id": 1, "sub_id": 11, "file":"ndjson2", "val":"both"}]
+            [{"id": 2, "sub_id": 21, "file":"ndjson1", "val":"both/multi 1"},{"id": 2, "sub_id": 21, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id": 2, "sub_id": 22, "file":"ndjson1", "val":"both/multi 1"},{"id": 2, "sub_id": 21, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id": 3, "sub_id": 31, "file":"ndjson1", "val":"both/multi 2"},{"id": 3, "sub_id": 31, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id": 3, "sub_id": 31, "file":"ndjson1", "val":"both/multi 2"},{"id": 3, "sub_id": 32, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id": 6, "sub_id": 62, "file":"ndjson1", "val":"both/multi"},{"id": 6, "sub_id": 62, "file":"ndjson2", "val":"both/multi"}]
+            [{"id": 6, "sub_id": 62, "file":"ndjson1", "val":"both/multi"},{"id": 6, "sub_id": 61, "file":"ndjson2", "val":"both/multi"}]
+            [{"id": 6, "sub_id": 61, "file":"ndjson1", "val":"both/multi"},{"id": 6, "sub_id": 62, "file":"ndjson2", "val":"both/multi"}]
+            [{"id": 6, "sub_id": 61, "file":"ndjson1", "val":"both/multi"},{"id": 6, "sub_id": 61, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":11, "sub_id":111, "file":"ndjson1", "val":"both"},{"id":11, "sub_id":111, "file":"ndjson2", "val":"both"}]
+            [{"id":12, "sub_id":121, "file":"ndjson1", "val":"both/multi 1"},{"id":12, "sub_id":121, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id":12, "sub_id":122, "file":"ndjson1", "val":"both/multi 1"},{"id":12, "sub_id":121, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id":13, "sub_id":131, "file":"ndjson1", "val":"both/multi 2"},{"id":13, "sub_id":131, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id":13, "sub_id":131, "file":"ndjson1", "val":"both/multi 2"},{"id":13, "sub_id":132, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id":16, "sub_id":162, "file":"ndjson1", "val":"both/multi"},{"id":16, "sub_id":162, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":16, "sub_id":162, "file":"ndjson1", "val":"both/multi"},{"id":16, "sub_id":161, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":16, "sub_id":161, "file":"ndjson1", "val":"both/multi"},{"id":16, "sub_id":162, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":16, "sub_id":161, "file":"ndjson1", "val":"both/multi"},{"id":16, "sub_id":161, "file":"ndjson2", "val":"both/multi"}]
         "#},
         str::from_utf8(&buf).unwrap()
     );
@@ -111,32 +121,32 @@ fn test_join_with_merging1() {
         "id",
         fout,
         Opts {
-            allow_no_key: false,
             merge: Some(1),
+            ..Default::default()
         },
     )
     .unwrap();
 
     assert_eq!(
         indoc! {r#"
-            {"file":"ndjson1","id":1,"sub_id":11,"val":"both"}
-            {"file":"ndjson1","id":2,"sub_id":21,"val":"both/multi 1"}
-            {"file":"ndjson1","id":2,"sub_id":22,"val":"both/multi 1"}
-            {"file":"ndjson1","id":3,"sub_id":31,"val":"both/multi 2"}
-            {"file":"ndjson1","id":3,"sub_id":31,"val":"both/multi 2"}
-            {"file":"ndjson1","id":6,"sub_id":62,"val":"both/multi"}
-            {"file":"ndjson1","id":6,"sub_id":62,"val":"both/multi"}
-            {"file":"ndjson1","id":6,"sub_id":61,"val":"both/multi"}
-            {"file":"ndjson1","id":6,"sub_id":61,"val":"both/multi"}
-            {"file":"ndjson1","id":11,"sub_id":111,"val":"both"}
-            {"file":"ndjson1","id":12,"sub_id":121,"val":"both/multi 1"}
-            {"file":"ndjson1","id":12,"sub_id":122,"val":"both/multi 1"}
-            {"file":"ndjson1","id":13,"sub_id":131,"val":"both/multi 2"}
-            {"file":"ndjson1","id":13,"sub_id":131,"val":"both/multi 2"}
-            {"file":"ndjson1","id":16,"sub_id":162,"val":"both/multi"}
-            {"file":"ndjson1","id":16,"sub_id":162,"val":"both/multi"}
-            {"file":"ndjson1","id":16,"sub_id":161,"val":"both/multi"}
-            {"file":"ndjson1","id":16,"sub_id":161,"val":"both/multi"}
+            {"id":1,"sub_id":11,"file":"ndjson1","val":"both"}
+            {"id":2,"sub_id":21,"file":"ndjson1","val":"both/multi 1"}
+            {"id":2,"sub_id":22,"file":"ndjson1","val":"both/multi 1"}
+            {"id":3,"sub_id":31,"file":"ndjson1","val":"both/multi 2"}
+            {"id":3,"sub_id":31,"file":"ndjson1","val":"both/multi 2"}
+            {"id":6,"sub_id":62,"file":"ndjson1","val":"both/multi"}
+            {"id":6,"sub_id":62,"file":"ndjson1","val":"both/multi"}
+            {"id":6,"sub_id":61,"file":"ndjson1","val":"both/multi"}
+            {"id":6,"sub_id":61,"file":"ndjson1","val":"both/multi"}
+            {"id":11,"sub_id":111,"file":"ndjson1","val":"both"}
+            {"id":12,"sub_id":121,"file":"ndjson1","val":"both/multi 1"}
+            {"id":12,"sub_id":122,"file":"ndjson1","val":"both/multi 1"}
+            {"id":13,"sub_id":131,"file":"ndjson1","val":"both/multi 2"}
+            {"id":13,"sub_id":131,"file":"ndjson1","val":"both/multi 2"}
+            {"id":16,"sub_id":162,"file":"ndjson1","val":"both/multi"}
+            {"id":16,"sub_id":162,"file":"ndjson1","val":"both/multi"}
+            {"id":16,"sub_id":161,"file":"ndjson1","val":"both/multi"}
+            {"id":16,"sub_id":161,"file":"ndjson1","val":"both/multi"}
         "#},
         str::from_utf8(&buf).unwrap()
     );
@@ -162,32 +172,32 @@ fn test_join_with_merging2() {
         "id",
         fout,
         Opts {
-            allow_no_key: false,
             merge: Some(2),
+            ..Default::default()
         },
     )
     .unwrap();
 
     assert_eq!(
         indoc! {r#"
-            {"file":"ndjson2","id":1,"sub_id":11,"val":"both"}
-            {"file":"ndjson2","id":2,"sub_id":21,"val":"both/multi 1"}
-            {"file":"ndjson2","id":2,"sub_id":21,"val":"both/multi 1"}
-            {"file":"ndjson2","id":3,"sub_id":31,"val":"both/multi 2"}
-            {"file":"ndjson2","id":3,"sub_id":32,"val":"both/multi 2"}
-            {"file":"ndjson2","id":6,"sub_id":62,"val":"both/multi"}
-            {"file":"ndjson2","id":6,"sub_id":61,"val":"both/multi"}
-            {"file":"ndjson2","id":6,"sub_id":62,"val":"both/multi"}
-            {"file":"ndjson2","id":6,"sub_id":61,"val":"both/multi"}
-            {"file":"ndjson2","id":11,"sub_id":111,"val":"both"}
-            {"file":"ndjson2","id":12,"sub_id":121,"val":"both/multi 1"}
-            {"file":"ndjson2","id":12,"sub_id":121,"val":"both/multi 1"}
-            {"file":"ndjson2","id":13,"sub_id":131,"val":"both/multi 2"}
-            {"file":"ndjson2","id":13,"sub_id":132,"val":"both/multi 2"}
-            {"file":"ndjson2","id":16,"sub_id":162,"val":"both/multi"}
-            {"file":"ndjson2","id":16,"sub_id":161,"val":"both/multi"}
-            {"file":"ndjson2","id":16,"sub_id":162,"val":"both/multi"}
-            {"file":"ndjson2","id":16,"sub_id":161,"val":"both/multi"}
+            {"id":1,"sub_id":11,"file":"ndjson2","val":"both"}
+            {"id":2,"sub_id":21,"file":"ndjson2","val":"both/multi 1"}
+            {"id":2,"sub_id":21,"file":"ndjson2","val":"both/multi 1"}
+            {"id":3,"sub_id":31,"file":"ndjson2","val":"both/multi 2"}
+            {"id":3,"sub_id":32,"file":"ndjson2","val":"both/multi 2"}
+            {"id":6,"sub_id":62,"file":"ndjson2","val":"both/multi"}
+            {"id":6,"sub_id":61,"file":"ndjson2","val":"both/multi"}
+            {"id":6,"sub_id":62,"file":"ndjson2","val":"both/multi"}
+            {"id":6,"sub_id":61,"file":"ndjson2","val":"both/multi"}
+            {"id":11,"sub_id":111,"file":"ndjson2","val":"both"}
+            {"id":12,"sub_id":121,"file":"ndjson2","val":"both/multi 1"}
+            {"id":12,"sub_id":121,"file":"ndjson2","val":"both/multi 1"}
+            {"id":13,"sub_id":131,"file":"ndjson2","val":"both/multi 2"}
+            {"id":13,"sub_id":132,"file":"ndjson2","val":"both/multi 2"}
+            {"id":16,"sub_id":162,"file":"ndjson2","val":"both/multi"}
+            {"id":16,"sub_id":161,"file":"ndjson2","val":"both/multi"}
+            {"id":16,"sub_id":162,"file":"ndjson2","val":"both/multi"}
+            {"id":16,"sub_id":161,"file":"ndjson2","val":"both/multi"}
         "#},
         str::from_utf8(&buf).unwrap()
     );
@@ -206,44 +216,116 @@ fn test_join_different_key() {
     let mut buf = Vec::new();
     let fout = Box::new(&mut buf);
 
-    join(
-        f1,
-        "id",
-        f2,
-        "id2",
-        fout,
-        Opts {
-            allow_no_key: false,
-            merge: None,
-        },
-    )
-    .unwrap();
+    join(f1, "id", f2, "id2", fout, Opts::default()).unwrap();
 
     assert_eq!(
         indoc! {r#"
-            [{"file":"ndjson1","id":1,"sub_id":11,"val":"both"},{"file":"ndjson2","id2":1,"sub_id":11,"val":"both"}]
-            [{"file":"ndjson1","id":2,"sub_id":21,"val":"both/multi 1"},{"file":"ndjson2","id2":2,"sub_id":21,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":2,"sub_id":22,"val":"both/multi 1"},{"file":"ndjson2","id2":2,"sub_id":21,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":3,"sub_id":31,"val":"both/multi 2"},{"file":"ndjson2","id2":3,"sub_id":31,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":3,"sub_id":31,"val":"both/multi 2"},{"file":"ndjson2","id2":3,"sub_id":32,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":6,"sub_id":62,"val":"both/multi"},{"file":"ndjson2","id2":6,"sub_id":62,"val":"both/multi"}]
-            [{"file":"ndjson1","id":6,"sub_id":62,"val":"both/multi"},{"file":"ndjson2","id2":6,"sub_id":61,"val":"both/multi"}]
-            [{"file":"ndjson1","id":6,"sub_id":61,"val":"both/multi"},{"file":"ndjson2","id2":6,"sub_id":62,"val":"both/multi"}]
-            [{"file":"ndjson1","id":6,"sub_id":61,"val":"both/multi"},{"file":"ndjson2","id2":6,"sub_id":61,"val":"both/multi"}]
-            [{"file":"ndjson1","id":11,"sub_id":111,"val":"both"},{"file":"ndjson2","id2":11,"sub_id":111,"val":"both"}]
-            [{"file":"ndjson1","id":12,"sub_id":121,"val":"both/multi 1"},{"file":"ndjson2","id2":12,"sub_id":121,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":12,"sub_id":122,"val":"both/multi 1"},{"file":"ndjson2","id2":12,"sub_id":121,"val":"both/multi 1"}]
-            [{"file":"ndjson1","id":13,"sub_id":131,"val":"both/multi 2"},{"file":"ndjson2","id2":13,"sub_id":131,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":13,"sub_id":131,"val":"both/multi 2"},{"file":"ndjson2","id2":13,"sub_id":132,"val":"both/multi 2"}]
-            [{"file":"ndjson1","id":16,"sub_id":162,"val":"both/multi"},{"file":"ndjson2","id2":16,"sub_id":162,"val":"both/multi"}]
-            [{"file":"ndjson1","id":16,"sub_id":162,"val":"both/multi"},{"file":"ndjson2","id2":16,"sub_id":161,"val":"both/multi"}]
-            [{"file":"ndjson1","id":16,"sub_id":161,"val":"both/multi"},{"file":"ndjson2","id2":16,"sub_id":162,"val":"both/multi"}]
-            [{"file":"ndjson1","id":16,"sub_id":161,"val":"both/multi"},{"file":"ndjson2","id2":16,"sub_id":161,"val":"both/multi"}]
+            [{"id": 1, "sub_id": 11, "file":"ndjson1", "val":"both"},{"id2": 1, "sub_id": 11, "file":"ndjson2", "val":"both"}]
+            [{"id": 2, "sub_id": 21, "file":"ndjson1", "val":"both/multi 1"},{"id2": 2, "sub_id": 21, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id": 2, "sub_id": 22, "file":"ndjson1", "val":"both/multi 1"},{"id2": 2, "sub_id": 21, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id": 3, "sub_id": 31, "file":"ndjson1", "val":"both/multi 2"},{"id2": 3, "sub_id": 31, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id": 3, "sub_id": 31, "file":"ndjson1", "val":"both/multi 2"},{"id2": 3, "sub_id": 32, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id": 6, "sub_id": 62, "file":"ndjson1", "val":"both/multi"},{"id2": 6, "sub_id": 62, "file":"ndjson2", "val":"both/multi"}]
+            [{"id": 6, "sub_id": 62, "file":"ndjson1", "val":"both/multi"},{"id2": 6, "sub_id": 61, "file":"ndjson2", "val":"both/multi"}]
+            [{"id": 6, "sub_id": 61, "file":"ndjson1", "val":"both/multi"},{"id2": 6, "sub_id": 62, "file":"ndjson2", "val":"both/multi"}]
+            [{"id": 6, "sub_id": 61, "file":"ndjson1", "val":"both/multi"},{"id2": 6, "sub_id": 61, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":11, "sub_id":111, "file":"ndjson1", "val":"both"},{"id2":11, "sub_id":111, "file":"ndjson2", "val":"both"}]
+            [{"id":12, "sub_id":121, "file":"ndjson1", "val":"both/multi 1"},{"id2":12, "sub_id":121, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id":12, "sub_id":122, "file":"ndjson1", "val":"both/multi 1"},{"id2":12, "sub_id":121, "file":"ndjson2", "val":"both/multi 1"}]
+            [{"id":13, "sub_id":131, "file":"ndjson1", "val":"both/multi 2"},{"id2":13, "sub_id":131, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id":13, "sub_id":131, "file":"ndjson1", "val":"both/multi 2"},{"id2":13, "sub_id":132, "file":"ndjson2", "val":"both/multi 2"}]
+            [{"id":16, "sub_id":162, "file":"ndjson1", "val":"both/multi"},{"id2":16, "sub_id":162, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":16, "sub_id":162, "file":"ndjson1", "val":"both/multi"},{"id2":16, "sub_id":161, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":16, "sub_id":161, "file":"ndjson1", "val":"both/multi"},{"id2":16, "sub_id":162, "file":"ndjson2", "val":"both/multi"}]
+            [{"id":16, "sub_id":161, "file":"ndjson1", "val":"both/multi"},{"id2":16, "sub_id":161, "file":"ndjson2", "val":"both/multi"}]
         "#},
         str::from_utf8(&buf).unwrap()
     );
 }
 
+#[test]
+fn test_check_keys() {
+    check_keys("id,sub_id", "/id,/sub_id").unwrap();
+
+    assert_eq!(
+        check_keys("a..b", "a").unwrap_err().to_string(),
+        "Invalid key path: a..b"
+    );
+    assert_eq!(
+        check_keys("id,x", "y").unwrap_err().to_string(),
+        "Number of join keys does not match: 'id,x' and 'y'"
+    );
+}
+
+#[test]
+fn test_check_opts() {
+    check_opts(&Opts::default()).unwrap();
+
+    for (opts, msg) in [
+        (
+            Opts {
+                merge: Some(3),
+                ..Default::default()
+            },
+            "File number of merging must be 1 or 2: 3",
+        ),
+        (
+            Opts {
+                semi: Some(1),
+                only_unmatched: Some(2),
+                ..Default::default()
+            },
+            "The anti-join and the semi-join cannot be used together",
+        ),
+        (
+            Opts {
+                semi: Some(1),
+                merge: Some(1),
+                ..Default::default()
+            },
+            "The anti-join and the semi-join cannot be used with merging or the outer join",
+        ),
+        (
+            Opts {
+                ignore_nulls: true,
+                ..Default::default()
+            },
+            "Ignoring nulls requires merging",
+        ),
+        (
+            Opts {
+                merge: Some(1),
+                prefer: vec![(1, "a..b".to_string())],
+                ..Default::default()
+            },
+            "Invalid key path: a..b",
+        ),
+        (
+            Opts {
+                group: Some((2, "items".to_string())),
+                outer: Some(Outer::Full),
+                ..Default::default()
+            },
+            "Grouping the records of FILE2 cannot output the unpaired records of FILE2",
+        ),
+    ]
+    .iter()
+    {
+        assert_eq!(check_opts(opts).unwrap_err().to_string(), *msg);
+
+        let r = join(
+            NDJSON1.as_bytes(),
+            "id",
+            NDJSON2.as_bytes(),
+            "id",
+            Box::new(io::sink()),
+            opts.clone(),
+        );
+
+        assert_eq!(r.unwrap_err().to_string(), *msg);
+    }
+}
+
 #[test]
 fn test_join_without_key() {
     let mut f1 = tempfile::tempfile().unwrap();
@@ -257,21 +339,11 @@ fn test_join_without_key() {
     let mut buf = Vec::new();
     let fout = Box::new(&mut buf);
 
-    let r = join(
-        f1,
-        "noid",
-        f2,
-        "noid",
-        fout,
-        Opts {
-            allow_no_key: false,
-            merge: None,
-        },
-    );
+    let r = join(f1, "noid", f2, "noid", fout, Opts::default());
 
     assert_eq!(
         r.err().unwrap().to_string(),
-        r#"Key 'noid' does not exist: {"id":1}"#
+        r#"FILE1:1:0: Key 'noid' does not exist: {"id":1}"#
     );
 }
 
@@ -296,7 +368,7 @@ fn test_join_allow_no_key() {
         fout,
         Opts {
             allow_no_key: true,
-            merge: None,
+            ..Default::default()
         },
     )
     .unwrap();
@@ -325,20 +397,2007 @@ fn test_join_no_obj() {
     let mut buf = Vec::new();
     let fout = Box::new(&mut buf);
 
-    let r = join(
+    let r = join(f1, "id", f2, "id", fout, Opts::default());
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        r#"FILE1:1:0: JSON in row is not Object type: [{"id":1}]"#
+    );
+}
+
+static OUTER_NDJSON1: &str = indoc! {r#"
+    {"id":1, "file":"ndjson1"}
+    {"id":2, "file":"ndjson1"}
+    {"id":4, "file":"ndjson1"}
+    {"id":4, "file":"ndjson1", "dup":true}
+"#};
+
+static OUTER_NDJSON2: &str = indoc! {r#"
+    {"id":1, "file":"ndjson2"}
+    {"id":3, "file":"ndjson2"}
+    {"id":4, "file":"ndjson2"}
+    {"id":5, "file":"ndjson2"}
+"#};
+
+#[test]
+fn test_join_outer_left() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", OUTER_NDJSON1).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(f2, "{}", OUTER_NDJSON2).unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
         f1,
         "id",
         f2,
         "id",
         fout,
         Opts {
-            allow_no_key: false,
-            merge: None,
+            outer: Some(Outer::Left),
+            ..Default::default()
         },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+            [{"id":2, "file":"ndjson1"},null]
+            [{"id":4, "file":"ndjson1"},{"id":4, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1", "dup":true},{"id":4, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
     );
+}
+
+#[test]
+fn test_join_outer_right_with_merging() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", OUTER_NDJSON1).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(f2, "{}", OUTER_NDJSON2).unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            merge: Some(1),
+            outer: Some(Outer::Right),
+            ..Default::default()
+        },
+    )
+    .unwrap();
 
     assert_eq!(
-        r.err().unwrap().to_string(),
-        r#"JSON in row is not Object type: [{"id":1}]"#
+        indoc! {r#"
+            {"id":1,"file":"ndjson1"}
+            {"id":3,"file":"ndjson2"}
+            {"id":4,"file":"ndjson1"}
+            {"id":4,"file":"ndjson1","dup":true}
+            {"id":5,"file":"ndjson2"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_outer_full() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", OUTER_NDJSON1).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(f2, "{}", OUTER_NDJSON2).unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            outer: Some(Outer::Full),
+            placeholder: json!({}),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+            [{"id":2, "file":"ndjson1"},{}]
+            [{},{"id":3, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1"},{"id":4, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1", "dup":true},{"id":4, "file":"ndjson2"}]
+            [{},{"id":5, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_only_unmatched() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", OUTER_NDJSON1).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(f2, "{}", OUTER_NDJSON2).unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            only_unmatched: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id":3, "file":"ndjson2"}
+            {"id":5, "file":"ndjson2"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_semi() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", NDJSON1).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(f2, "{}", NDJSON2).unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            semi: Some(1),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id": 1, "sub_id": 11, "file":"ndjson1", "val":"both"}
+            {"id": 2, "sub_id": 21, "file":"ndjson1", "val":"both/multi 1"}
+            {"id": 2, "sub_id": 22, "file":"ndjson1", "val":"both/multi 1"}
+            {"id": 3, "sub_id": 31, "file":"ndjson1", "val":"both/multi 2"}
+            {"id": 6, "sub_id": 62, "file":"ndjson1", "val":"both/multi"}
+            {"id": 6, "sub_id": 61, "file":"ndjson1", "val":"both/multi"}
+            {"id":11, "sub_id":111, "file":"ndjson1", "val":"both"}
+            {"id":12, "sub_id":121, "file":"ndjson1", "val":"both/multi 1"}
+            {"id":12, "sub_id":122, "file":"ndjson1", "val":"both/multi 1"}
+            {"id":13, "sub_id":131, "file":"ndjson1", "val":"both/multi 2"}
+            {"id":16, "sub_id":162, "file":"ndjson1", "val":"both/multi"}
+            {"id":16, "sub_id":161, "file":"ndjson1", "val":"both/multi"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_semi2() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", NDJSON1).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(f2, "{}", NDJSON2).unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            semi: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id": 1, "sub_id": 11, "file":"ndjson2", "val":"both"}
+            {"id": 2, "sub_id": 21, "file":"ndjson2", "val":"both/multi 1"}
+            {"id": 3, "sub_id": 31, "file":"ndjson2", "val":"both/multi 2"}
+            {"id": 3, "sub_id": 32, "file":"ndjson2", "val":"both/multi 2"}
+            {"id": 6, "sub_id": 62, "file":"ndjson2", "val":"both/multi"}
+            {"id": 6, "sub_id": 61, "file":"ndjson2", "val":"both/multi"}
+            {"id":11, "sub_id":111, "file":"ndjson2", "val":"both"}
+            {"id":12, "sub_id":121, "file":"ndjson2", "val":"both/multi 1"}
+            {"id":13, "sub_id":131, "file":"ndjson2", "val":"both/multi 2"}
+            {"id":13, "sub_id":132, "file":"ndjson2", "val":"both/multi 2"}
+            {"id":16, "sub_id":162, "file":"ndjson2", "val":"both/multi"}
+            {"id":16, "sub_id":161, "file":"ndjson2", "val":"both/multi"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_skip_blocks() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", concat!(r#"{"id":1}"#, "\n", r#"{"id":4}"#)).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        concat!(
+            r#"{"id":1}"#,
+            "\n",
+            r#"{"id":2}"#,
+            "\n",
+            r#"{"id":3}"#,
+            "\n",
+            r#"{"id":4}"#
+        )
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(f1, "id", f2, "id", fout, Opts::default()).unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":1},{"id":1}]
+            [{"id":4},{"id":4}]
+        "#},
+        str::from_utf8(&buf).unwrap()
     );
 }
+
+#[test]
+fn test_join_mixed_key_types() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(
+        f1,
+        "{}",
+        indoc! {r#"
+        {"id":null, "file":"ndjson1"}
+        {"id":true, "file":"ndjson1"}
+        {"id":2, "file":"ndjson1"}
+        {"id":10, "file":"ndjson1"}
+        {"id":"10", "file":"ndjson1"}
+        {"id":[1,2], "file":"ndjson1"}
+    "#}
+    )
+    .unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        indoc! {r#"
+        {"id":false, "file":"ndjson2"}
+        {"id":10.0, "file":"ndjson2"}
+        {"id":"10", "file":"ndjson2"}
+        {"id":"9", "file":"ndjson2"}
+        {"id":[1,2], "file":"ndjson2"}
+        {"id":{}, "file":"ndjson2"}
+    "#}
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(f1, "id", f2, "id", fout, Opts::default()).unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":10, "file":"ndjson1"},{"id":10.0, "file":"ndjson2"}]
+            [{"id":"10", "file":"ndjson1"},{"id":"10", "file":"ndjson2"}]
+            [{"id":[1,2], "file":"ndjson1"},{"id":[1,2], "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_big_numbers() {
+    let ndjson1 = indoc! {r#"
+        {"id":0, "file":"ndjson1"}
+        {"id":10, "file":"ndjson1"}
+        {"id":12345678901234567890123, "file":"ndjson1"}
+        {"id":1e400, "file":"ndjson1"}
+    "#};
+    let ndjson2 = indoc! {r#"
+        {"id":-0.0, "file":"ndjson2"}
+        {"id":1e1, "file":"ndjson2"}
+        {"id":12345678901234567890124, "file":"ndjson2"}
+        {"id":10.00e399, "file":"ndjson2"}
+    "#};
+
+    for hash in [None, Some(2)].iter() {
+        let mut buf = Vec::new();
+        let fout = Box::new(&mut buf);
+
+        join(
+            ndjson1.as_bytes(),
+            "id",
+            ndjson2.as_bytes(),
+            "id",
+            fout,
+            Opts {
+                hash: *hash,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            indoc! {r#"
+                [{"id":0, "file":"ndjson1"},{"id":-0.0, "file":"ndjson2"}]
+                [{"id":10, "file":"ndjson1"},{"id":1e1, "file":"ndjson2"}]
+                [{"id":1e400, "file":"ndjson1"},{"id":10.00e399, "file":"ndjson2"}]
+            "#},
+            str::from_utf8(&buf).unwrap(),
+            "hash: {:?}",
+            hash
+        );
+    }
+}
+
+#[test]
+fn test_join_not_sorted() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", concat!(r#"{"id":1}"#, "\n", r#"{"id":3}"#)).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        concat!(r#"{"id":1}"#, "\n", r#"{"id":3}"#, "\n", r#"{"id":2}"#)
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let r = join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            file_names: ("a.ndjson".to_string(), "b.ndjson".to_string()),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        "b.ndjson:3:18: Not sorted by the join key: 2 comes after 3"
+    );
+}
+
+#[test]
+fn test_join_nocheck_order() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(f1, "{}", concat!(r#"{"id":1}"#, "\n", r#"{"id":3}"#)).unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        concat!(r#"{"id":1}"#, "\n", r#"{"id":3}"#, "\n", r#"{"id":2}"#)
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "id",
+        f2,
+        "id",
+        fout,
+        Opts {
+            check_order: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":1},{"id":1}]
+            [{"id":3},{"id":3}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_key_path() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(
+        f1,
+        "{}",
+        indoc! {r#"
+        {"user":{"id":1}, "file":"ndjson1"}
+        {"user":{"id":2}, "file":"ndjson1"}
+        {"user":{}, "file":"ndjson1"}
+    "#}
+    )
+    .unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        indoc! {r#"
+        {"meta":{"ids":[0,1]}, "file":"ndjson2"}
+        {"meta":{"ids":[0,2]}, "file":"ndjson2"}
+    "#}
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let r = join(f1, "user.id", f2, "meta.ids[1]", fout, Opts::default());
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        r#"FILE1:3:72: Key 'user.id' does not exist: {"user":{}, "file":"ndjson1"}"#
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            [{"user":{"id":1}, "file":"ndjson1"},{"meta":{"ids":[0,1]}, "file":"ndjson2"}]
+            [{"user":{"id":2}, "file":"ndjson1"},{"meta":{"ids":[0,2]}, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_json_pointer() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(
+        f1,
+        "{}",
+        indoc! {r#"
+        {"a.b":{"c/d":1}, "file":"ndjson1"}
+        {"a.b":{"c/d":2}, "file":"ndjson1"}
+    "#}
+    )
+    .unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        indoc! {r#"
+        {"a.b":[2], "file":"ndjson2"}
+    "#}
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(f1, "/a.b/c~1d", f2, r"a\.b[0]", fout, Opts::default()).unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"a.b":{"c/d":2}, "file":"ndjson1"},{"a.b":[2], "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_composite_key() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(
+        f1,
+        "{}",
+        indoc! {r#"
+        {"tenant_id":1, "user_id":2, "file":"ndjson1"}
+        {"tenant_id":1, "user_id":3, "file":"ndjson1"}
+        {"tenant_id":2, "user_id":1, "file":"ndjson1"}
+    "#}
+    )
+    .unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        indoc! {r#"
+        {"tenant":1, "user":{"id":1}, "file":"ndjson2"}
+        {"tenant":1, "user":{"id":3}, "file":"ndjson2"}
+        {"tenant":2, "user":{"id":1}, "file":"ndjson2"}
+    "#}
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "tenant_id,user_id",
+        f2,
+        "tenant,/user/id",
+        fout,
+        Opts::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"tenant_id":1, "user_id":3, "file":"ndjson1"},{"tenant":1, "user":{"id":3}, "file":"ndjson2"}]
+            [{"tenant_id":2, "user_id":1, "file":"ndjson1"},{"tenant":2, "user":{"id":1}, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_composite_key_allow_no_key() {
+    let mut f1 = tempfile::tempfile().unwrap();
+    write!(
+        f1,
+        "{}",
+        indoc! {r#"
+        {"a":1}
+        {"a":1, "b":1}
+    "#}
+    )
+    .unwrap();
+    f1.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut f2 = tempfile::tempfile().unwrap();
+    write!(
+        f2,
+        "{}",
+        indoc! {r#"
+        {"a":1, "c":2}
+        {"a":1, "b":1, "c":3}
+    "#}
+    )
+    .unwrap();
+    f2.seek(io::SeekFrom::Start(0)).unwrap();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        f1,
+        "a,b",
+        f2,
+        "a,b",
+        fout,
+        Opts {
+            allow_no_key: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"a":1},{"a":1, "c":2}]
+            [{"a":1, "b":1},{"a":1, "b":1, "c":3}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_without_seek() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        OUTER_NDJSON1.as_bytes(),
+        "id",
+        OUTER_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            outer: Some(Outer::Full),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+            [{"id":2, "file":"ndjson1"},null]
+            [null,{"id":3, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1"},{"id":4, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1", "dup":true},{"id":4, "file":"ndjson2"}]
+            [null,{"id":5, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_verbatim() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        indoc! {r#"
+            { "id" : 1.0, "price": 1.50, "name": "caf\u00e9" }
+            {"id":2,"big":12345678901234567890123}
+        "#}
+        .as_bytes(),
+        "id",
+        indoc! {r#"
+            {"z":0, "id":1}
+            {"z":0, "id":2}
+        "#}
+        .as_bytes(),
+        "id",
+        fout,
+        Opts::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{ "id" : 1.0, "price": 1.50, "name": "caf\u00e9" },{"z":0, "id":1}]
+            [{"id":2,"big":12345678901234567890123},{"z":0, "id":2}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_merge_preserve_order() {
+    let ndjson1 = indoc! {r#"
+        {"id":1,"price":1.50,"name":"a","tags":{"y":1,"x":2}}
+        {"id":2,"big":12345678901234567890123}
+    "#};
+
+    let ndjson2 = indoc! {r#"
+        {"stock":10,"id":1,"price":2.00,"tags":{"z":3,"x":4}}
+        {"id":2.0,"big":1e+400}
+    "#};
+
+    for (merge, expected) in &[
+        (
+            1,
+            indoc! {r#"
+                {"id":1,"price":1.50,"name":"a","tags":{"y":1,"x":2,"z":3},"stock":10}
+                {"id":2,"big":12345678901234567890123}
+            "#},
+        ),
+        (
+            2,
+            indoc! {r#"
+                {"id":1,"price":2.00,"name":"a","tags":{"y":1,"x":4,"z":3},"stock":10}
+                {"id":2.0,"big":1e+400}
+            "#},
+        ),
+    ] {
+        let mut buf = Vec::new();
+        let fout = Box::new(&mut buf);
+
+        join(
+            ndjson1.as_bytes(),
+            "id",
+            ndjson2.as_bytes(),
+            "id",
+            fout,
+            Opts {
+                merge: Some(*merge),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(*expected, str::from_utf8(&buf).unwrap());
+    }
+}
+
+#[test]
+fn test_join_merge_strategy() {
+    let ndjson1 = indoc! {r#"
+        {"id":1,"a":{"x":1,"y":[1,2]},"n":5,"m":null,"t":[{"k":1}]}
+    "#};
+
+    let ndjson2 = indoc! {r#"
+        {"id":1,"a":{"z":3,"y":[2,3]},"n":null,"m":6,"t":[{"k":1.0},{"k":2}]}
+    "#};
+
+    let cases = vec![
+        (
+            2,
+            MergeStrategy::Shallow,
+            false,
+            r#"{"id":1,"a":{"z":3,"y":[2,3]},"n":null,"m":6,"t":[{"k":1.0},{"k":2}]}"#,
+        ),
+        (
+            1,
+            MergeStrategy::Shallow,
+            false,
+            r#"{"id":1,"a":{"x":1,"y":[1,2]},"n":5,"m":null,"t":[{"k":1}]}"#,
+        ),
+        (
+            2,
+            MergeStrategy::Deep,
+            false,
+            r#"{"id":1,"a":{"x":1,"y":[2,3],"z":3},"n":null,"m":6,"t":[{"k":1.0},{"k":2}]}"#,
+        ),
+        (
+            2,
+            MergeStrategy::ArrayConcat,
+            false,
+            r#"{"id":1,"a":{"x":1,"y":[1,2,2,3],"z":3},"n":null,"m":6,"t":[{"k":1},{"k":1.0},{"k":2}]}"#,
+        ),
+        (
+            2,
+            MergeStrategy::ArrayUnion,
+            false,
+            r#"{"id":1,"a":{"x":1,"y":[1,2,3],"z":3},"n":null,"m":6,"t":[{"k":1},{"k":2}]}"#,
+        ),
+        (
+            2,
+            MergeStrategy::Deep,
+            true,
+            r#"{"id":1,"a":{"x":1,"y":[2,3],"z":3},"n":5,"m":6,"t":[{"k":1.0},{"k":2}]}"#,
+        ),
+        (
+            1,
+            MergeStrategy::Deep,
+            true,
+            r#"{"id":1,"a":{"x":1,"y":[1,2],"z":3},"n":5,"m":6,"t":[{"k":1}]}"#,
+        ),
+        (
+            1,
+            MergeStrategy::Shallow,
+            true,
+            r#"{"id":1,"a":{"x":1,"y":[1,2]},"n":5,"m":6,"t":[{"k":1}]}"#,
+        ),
+    ];
+
+    for (merge, strategy, ignore_nulls, expected) in cases {
+        let mut buf = Vec::new();
+        let fout = Box::new(&mut buf);
+
+        join(
+            ndjson1.as_bytes(),
+            "id",
+            ndjson2.as_bytes(),
+            "id",
+            fout,
+            Opts {
+                merge: Some(merge),
+                merge_strategy: strategy,
+                ignore_nulls,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            format!("{}\n", expected),
+            str::from_utf8(&buf).unwrap(),
+            "{:?} (merge: {}, ignore_nulls: {})",
+            strategy,
+            merge,
+            ignore_nulls
+        );
+    }
+}
+
+#[test]
+fn test_join_merge_prefixes() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        OUTER_NDJSON1.as_bytes(),
+        "id",
+        OUTER_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            merge: Some(1),
+            prefixes: ("a_".to_string(), "b_".to_string()),
+            outer: Some(Outer::Left),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id":1,"a_file":"ndjson1","b_file":"ndjson2"}
+            {"id":2,"a_file":"ndjson1"}
+            {"id":4,"a_file":"ndjson1","b_file":"ndjson2"}
+            {"id":4,"a_file":"ndjson1","a_dup":true,"b_file":"ndjson2"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_merge_nest() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        indoc! {r#"
+            {"user":{"id":1},"tenant":1,"price":10}
+        "#}
+        .as_bytes(),
+        "tenant,user.id",
+        indoc! {r#"
+            {"tenant_id":1,"price":11,"user_id":1}
+        "#}
+        .as_bytes(),
+        "tenant_id,user_id",
+        fout,
+        Opts {
+            merge: Some(2),
+            nest: Some(("old".to_string(), "new".to_string())),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"user":{"id":1},"tenant":1,"old":{"price":10},"tenant_id":1,"user_id":1,"new":{"price":11}}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+static CONFLICT_NDJSON1: &str = indoc! {r#"
+    {"id":1,"price":10,"a":{"b.c":1},"x":null}
+    {"id":2,"price":20}
+"#};
+
+static CONFLICT_NDJSON2: &str = indoc! {r#"
+    {"id":1,"price":11,"a":{"b.c":2},"x":1}
+    {"id":2,"price":20.0}
+"#};
+
+#[test]
+fn test_join_merge_conflicts() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conflicts.ndjson");
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let stats = join(
+        CONFLICT_NDJSON1.as_bytes(),
+        "id",
+        CONFLICT_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            merge: Some(1),
+            on_conflict: OnConflict::Report(path.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id":1,"price":10,"a":{"b.c":1},"x":null}
+            {"id":2,"price":20}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+
+    assert_eq!(3, stats.merge_conflicts);
+
+    assert_eq!(
+        indoc! {r#"
+            {"key":1,"path":"price","left":10,"right":11}
+            {"key":1,"path":"a.b\\.c","left":1,"right":2}
+            {"key":1,"path":"x","left":null,"right":1}
+        "#},
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[test]
+fn test_join_merge_conflicts_ignore_nulls() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let stats = join(
+        CONFLICT_NDJSON1.as_bytes(),
+        "id",
+        CONFLICT_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            merge: Some(2),
+            ignore_nulls: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id":1,"price":11,"a":{"b.c":2},"x":1}
+            {"id":2,"price":20.0}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+
+    assert_eq!(2, stats.merge_conflicts);
+}
+
+#[test]
+fn test_join_merge_conflict_error() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let r = join(
+        CONFLICT_NDJSON1.as_bytes(),
+        "id",
+        CONFLICT_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            merge: Some(1),
+            on_conflict: OnConflict::Fail,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        "FILE1:1:0: Merge conflict at 'price' with FILE2:1:0: 10 and 11"
+    );
+}
+
+#[test]
+fn test_join_merge_prefer() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let stats = join(
+        indoc! {r#"
+            {"id":1,"price":10,"stock":1,"name":"A","addr":{"city":"x","zip":"1"},"tag":"a"}
+        "#}
+        .as_bytes(),
+        "id",
+        indoc! {r#"
+            {"id":1,"price":11,"stock":2,"name":"B","addr":{"city":"y","zip":"2"},"tag":"b"}
+        "#}
+        .as_bytes(),
+        "id",
+        fout,
+        Opts {
+            merge: Some(1),
+            prefer: vec![
+                (2, "price,stock".to_string()),
+                (2, "/addr".to_string()),
+                (1, "addr.zip".to_string()),
+            ],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id":1,"price":11,"stock":2,"name":"A","addr":{"city":"y","zip":"1"},"tag":"a"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+
+    assert_eq!(6, stats.merge_conflicts);
+}
+
+static GROUP_NDJSON1: &str = indoc! {r#"
+    {"id":1,"name":"a"}
+    {"id":2,"name":"b"}
+    {"id":2,"name":"c"}
+    {"id":3,"name":"d"}
+"#};
+
+static GROUP_NDJSON2: &str = indoc! {r#"
+    {"id":2,"item":"x"}
+    {"id":2,"item":"y"}
+    {"id":3,"item":"z"}
+    {"id":4,"item":"w"}
+"#};
+
+#[test]
+fn test_join_group() {
+    for hash in &[None, Some(2)] {
+        let mut buf = Vec::new();
+        let fout = Box::new(&mut buf);
+
+        let stats = join(
+            GROUP_NDJSON1.as_bytes(),
+            "id",
+            GROUP_NDJSON2.as_bytes(),
+            "id",
+            fout,
+            Opts {
+                group: Some((2, "items".to_string())),
+                outer: Some(Outer::Left),
+                hash: *hash,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            indoc! {r#"
+                {"id":1,"name":"a","items":[]}
+                {"id":2,"name":"b","items":[{"id":2,"item":"x"},{"id":2,"item":"y"}]}
+                {"id":2,"name":"c","items":[{"id":2,"item":"x"},{"id":2,"item":"y"}]}
+                {"id":3,"name":"d","items":[{"id":3,"item":"z"}]}
+            "#},
+            str::from_utf8(&buf).unwrap(),
+            "hash: {:?}",
+            hash
+        );
+
+        assert_eq!(4, stats.output_rows);
+    }
+}
+
+#[test]
+fn test_join_group_file1() {
+    for hash in &[None, Some(1)] {
+        let mut buf = Vec::new();
+        let fout = Box::new(&mut buf);
+
+        join(
+            GROUP_NDJSON1.as_bytes(),
+            "id",
+            GROUP_NDJSON2.as_bytes(),
+            "id",
+            fout,
+            Opts {
+                group: Some((1, "users".to_string())),
+                hash: *hash,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            indoc! {r#"
+                {"id":2,"item":"x","users":[{"id":2,"name":"b"},{"id":2,"name":"c"}]}
+                {"id":2,"item":"y","users":[{"id":2,"name":"b"},{"id":2,"name":"c"}]}
+                {"id":3,"item":"z","users":[{"id":3,"name":"d"}]}
+            "#},
+            str::from_utf8(&buf).unwrap(),
+            "hash: {:?}",
+            hash
+        );
+    }
+}
+
+#[test]
+fn test_join_group_hash_other_file() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let r = join(
+        GROUP_NDJSON1.as_bytes(),
+        "id",
+        GROUP_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            group: Some((2, "items".to_string())),
+            hash: Some(1),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        "Grouping the records of FILE2 requires loading FILE2 into memory"
+    );
+}
+
+#[test]
+fn test_join_object_key_member_order() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        indoc! {r#"
+            {"k":{"a":1,"b":2},"file":"ndjson1"}
+        "#}
+        .as_bytes(),
+        "k",
+        indoc! {r#"
+            {"k":{"b":2,"a":1},"file":"ndjson2"}
+        "#}
+        .as_bytes(),
+        "k",
+        fout,
+        Opts {
+            hash: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"k":{"a":1,"b":2},"file":"ndjson1"},{"k":{"b":2,"a":1},"file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_output_shape_object() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        OUTER_NDJSON1.as_bytes(),
+        "id",
+        OUTER_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            outer: Some(Outer::Full),
+            output_shape: OutputShape::Object,
+            names: ("orders".to_string(), "customers".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"key":1,"orders":{"id":1, "file":"ndjson1"},"customers":{"id":1, "file":"ndjson2"}}
+            {"key":2,"orders":{"id":2, "file":"ndjson1"},"customers":null}
+            {"key":3,"orders":null,"customers":{"id":3, "file":"ndjson2"}}
+            {"key":4,"orders":{"id":4, "file":"ndjson1"},"customers":{"id":4, "file":"ndjson2"}}
+            {"key":4,"orders":{"id":4, "file":"ndjson1", "dup":true},"customers":{"id":4, "file":"ndjson2"}}
+            {"key":5,"orders":null,"customers":{"id":5, "file":"ndjson2"}}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_output_shape_object_composite_key() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        indoc! {r#"
+            {"a":1,"b":"x"}
+        "#}
+        .as_bytes(),
+        "a,b",
+        indoc! {r#"
+            {"b":"x","a":1.0}
+        "#}
+        .as_bytes(),
+        "a,b",
+        fout,
+        Opts {
+            output_shape: OutputShape::Object,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"key":[1,"x"],"left":{"a":1,"b":"x"},"right":{"b":"x","a":1.0}}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_spill_block() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        NDJSON1.as_bytes(),
+        "id",
+        NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            block_buffer_size: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let mut expected = Vec::new();
+
+    join(
+        NDJSON1.as_bytes(),
+        "id",
+        NDJSON2.as_bytes(),
+        "id",
+        Box::new(&mut expected),
+        Opts::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        str::from_utf8(&expected).unwrap(),
+        str::from_utf8(&buf).unwrap()
+    );
+    assert_eq!(18, str::from_utf8(&buf).unwrap().lines().count());
+}
+
+static UNSORTED_NDJSON1: &str = indoc! {r#"
+    {"id":4, "file":"ndjson1"}
+    {"id":1, "file":"ndjson1"}
+    {"id":2, "file":"ndjson1"}
+    {"id":4, "file":"ndjson1", "dup":true}
+"#};
+
+static UNSORTED_NDJSON2: &str = indoc! {r#"
+    {"id":5, "file":"ndjson2"}
+    {"id":4.0, "file":"ndjson2"}
+    {"id":1, "file":"ndjson2"}
+    {"id":3, "file":"ndjson2"}
+"#};
+
+#[test]
+fn test_join_hash() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        UNSORTED_NDJSON1.as_bytes(),
+        "id",
+        UNSORTED_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            hash: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":4, "file":"ndjson1"},{"id":4.0, "file":"ndjson2"}]
+            [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1", "dup":true},{"id":4.0, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_hash_outer_full() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        UNSORTED_NDJSON1.as_bytes(),
+        "id",
+        UNSORTED_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            hash: Some(1),
+            outer: Some(Outer::Full),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [null,{"id":5, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1"},{"id":4.0, "file":"ndjson2"}]
+            [{"id":4, "file":"ndjson1", "dup":true},{"id":4.0, "file":"ndjson2"}]
+            [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+            [null,{"id":3, "file":"ndjson2"}]
+            [{"id":2, "file":"ndjson1"},null]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_hash_semi() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        UNSORTED_NDJSON1.as_bytes(),
+        "id",
+        UNSORTED_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            hash: Some(1),
+            semi: Some(1),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            {"id":4, "file":"ndjson1"}
+            {"id":4, "file":"ndjson1", "dup":true}
+            {"id":1, "file":"ndjson1"}
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_sort() {
+    for memory_limit in &[1, 1024] {
+        let mut buf = Vec::new();
+        let fout = Box::new(&mut buf);
+
+        join(
+            UNSORTED_NDJSON1.as_bytes(),
+            "id",
+            UNSORTED_NDJSON2.as_bytes(),
+            "id",
+            fout,
+            Opts {
+                sort: true,
+                memory_limit: *memory_limit,
+                outer: Some(Outer::Full),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            indoc! {r#"
+                [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+                [{"id":2, "file":"ndjson1"},null]
+                [null,{"id":3, "file":"ndjson2"}]
+                [{"id":4, "file":"ndjson1"},{"id":4.0, "file":"ndjson2"}]
+                [{"id":4, "file":"ndjson1", "dup":true},{"id":4.0, "file":"ndjson2"}]
+                [null,{"id":5, "file":"ndjson2"}]
+            "#},
+            str::from_utf8(&buf).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_join_sort_multi_pass() {
+    // A chunk for each line: the chunks are merged in more than one pass
+    let n = 319;
+    let ndjson1: String = (0..n)
+        .map(|i| format!("{{\"id\":{},\"n\":{}}}\n", i * 37 % 50, i))
+        .collect();
+    let ndjson2: String = (0..50)
+        .rev()
+        .map(|id| format!("{{\"id\":{}}}\n", id))
+        .collect();
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        ndjson1.as_bytes(),
+        "id",
+        ndjson2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            sort: true,
+            memory_limit: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // The lines with the same key keep the order of the input
+    let expected: String = (0..50)
+        .flat_map(|id| {
+            (0..n)
+                .filter(move |i| i * 37 % 50 == id)
+                .map(move |i| format!("[{{\"id\":{},\"n\":{}}},{{\"id\":{}}}]\n", id, i, id))
+        })
+        .collect();
+
+    assert_eq!(expected, str::from_utf8(&buf).unwrap());
+}
+
+#[test]
+fn test_join_invalid_json() {
+    let long = "x".repeat(300);
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let r = join(
+        concat!(r#"{"id":1}"#, "\n", r#"{"id":2}"#).as_bytes(),
+        "id",
+        format!("{{\"id\":1}}\n{{\"id\":2, {}}}\n", long).as_bytes(),
+        "id",
+        fout,
+        Opts {
+            file_names: ("a.ndjson".to_string(), "b.ndjson".to_string()),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        format!(
+            r#"b.ndjson:2:9: Failed to parse JSON: key must be a string at line 1 column 10: {{"id":2, {}..."#,
+            &long[..191]
+        )
+    );
+}
+
+#[test]
+fn test_join_invalid_utf8() {
+    let ndjson1 = b"{\"id\":1}\n{\"id\":2,\"x\":\"\xff\"}\n{\"id\":3}\n";
+    let ndjson2 = "{\"id\":1}\n{\"id\":2}\n{\"id\":3}\n";
+
+    let r = join(
+        &ndjson1[..],
+        "id",
+        ndjson2.as_bytes(),
+        "id",
+        Box::new(io::sink()),
+        Opts {
+            file_names: ("a.ndjson".to_string(), "b.ndjson".to_string()),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        "a.ndjson:2:9: Invalid UTF-8: invalid utf-8 sequence of 1 bytes from index 13: {\"id\":2,\"x\":\"\u{fffd}\"}"
+    );
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        &ndjson1[..],
+        "id",
+        ndjson2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            on_error: OnError::Skip,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        "[{\"id\":1},{\"id\":1}]\n[{\"id\":3},{\"id\":3}]\n",
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+static MALFORMED_NDJSON: &str = indoc! {r#"
+    {"id":1, "file":"ndjson1"}
+    {"id":2,
+    [{"id":3}]
+    {"noid":4}
+    {"id":5, "file":"ndjson1"}
+"#};
+
+#[test]
+fn test_join_on_error_skip() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        MALFORMED_NDJSON.as_bytes(),
+        "id",
+        OUTER_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            on_error: OnError::Skip,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [{"id":1, "file":"ndjson1"},{"id":1, "file":"ndjson2"}]
+            [{"id":5, "file":"ndjson1"},{"id":5, "file":"ndjson2"}]
+        "#},
+        str::from_utf8(&buf).unwrap()
+    );
+}
+
+#[test]
+fn test_join_on_error_quarantine() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rejected.ndjson");
+
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    join(
+        MALFORMED_NDJSON.as_bytes(),
+        "id",
+        OUTER_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            on_error: OnError::Quarantine(path.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(2, str::from_utf8(&buf).unwrap().lines().count());
+
+    assert_eq!(
+        indoc! {r#"
+            {"file":"FILE1","line":2,"offset":27,"error":"Failed to parse JSON: EOF while parsing a value at line 2 column 0: {\"id\":2,","raw":"{\"id\":2,"}
+            {"file":"FILE1","line":3,"offset":36,"error":"JSON in row is not Object type: [{\"id\":3}]","raw":"[{\"id\":3}]"}
+            {"file":"FILE1","line":4,"offset":47,"error":"Key 'id' does not exist: {\"noid\":4}","raw":"{\"noid\":4}"}
+        "#},
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[test]
+fn test_join_max_errors() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let r = join(
+        MALFORMED_NDJSON.as_bytes(),
+        "id",
+        OUTER_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            on_error: OnError::Skip,
+            max_errors: Some(2),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        r.err().unwrap().to_string(),
+        r#"Too many malformed lines (> 2): FILE1:4:47: Key 'id' does not exist: {"noid":4}"#
+    );
+}
+
+#[test]
+fn test_join_stats() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let stats = join(
+        NDJSON1.as_bytes(),
+        "id",
+        NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        json!({
+            "file1": {"lines": 14, "distinct_keys": 10, "unmatched_keys": 2, "largest_block": 2},
+            "file2": {"lines": 14, "distinct_keys": 10, "unmatched_keys": 2, "largest_block": 2},
+            "matched_keys": 8,
+            "output_rows": 18,
+            "malformed_lines": 0,
+            "merge_conflicts": 0,
+        }),
+        without_elapsed(&stats)
+    );
+}
+
+#[test]
+fn test_join_hash_stats() {
+    let mut buf = Vec::new();
+    let fout = Box::new(&mut buf);
+
+    let stats = join(
+        MALFORMED_NDJSON.as_bytes(),
+        "id",
+        UNSORTED_NDJSON2.as_bytes(),
+        "id",
+        fout,
+        Opts {
+            hash: Some(2),
+            on_error: OnError::Skip,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        json!({
+            "file1": {"lines": 5, "distinct_keys": null, "unmatched_keys": null, "largest_block": null},
+            "file2": {"lines": 4, "distinct_keys": 4, "unmatched_keys": 2, "largest_block": 1},
+            "matched_keys": 2,
+            "output_rows": 2,
+            "malformed_lines": 3,
+            "merge_conflicts": 0,
+        }),
+        without_elapsed(&stats)
+    );
+}
+
+fn without_elapsed(stats: &Stats) -> serde_json::Value {
+    let mut json = stats.to_json();
+    json.as_object_mut().unwrap().remove("elapsed_sec");
+    json
+}
+
+fn pos(line: u64, offset: u64) -> Option<Position> {
+    Some(Position { line, offset })
+}
+
+#[test]
+fn test_rows() {
+    let ndjson1 = indoc! {r#"
+        {"id":1,"a":1}
+        {"id":2,"a":2}
+        {"id":2,"a":3}
+    "#};
+
+    let ndjson2 = indoc! {r#"
+        {"id":2,"b":1}
+        {"id":3,"b":2}
+    "#};
+
+    let rows = rows(
+        ndjson1.as_bytes(),
+        "id",
+        ndjson2.as_bytes(),
+        "id",
+        Opts {
+            outer: Some(Outer::Full),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec![
+            JoinedRow {
+                key: json!(1),
+                left: Some(json!({"id":1,"a":1})),
+                right: None,
+                left_pos: pos(1, 0),
+                right_pos: None,
+            },
+            JoinedRow {
+                key: json!(2),
+                left: Some(json!({"id":2,"a":2})),
+                right: Some(json!({"id":2,"b":1})),
+                left_pos: pos(2, 15),
+                right_pos: pos(1, 0),
+            },
+            JoinedRow {
+                key: json!(2),
+                left: Some(json!({"id":2,"a":3})),
+                right: Some(json!({"id":2,"b":1})),
+                left_pos: pos(3, 30),
+                right_pos: pos(1, 0),
+            },
+            JoinedRow {
+                key: json!(3),
+                left: None,
+                right: Some(json!({"id":3,"b":2})),
+                left_pos: None,
+                right_pos: pos(2, 15),
+            },
+        ],
+        rows.collect::<anyhow::Result<Vec<_>>>().unwrap()
+    );
+}
+
+#[test]
+fn test_rows_sort() {
+    let ndjson1 = indoc! {r#"
+        {"id":2,"a":1}
+        {"id":1,"a":2}
+    "#};
+
+    let ndjson2 = indoc! {r#"
+        {"id":1,"b":1}
+        {"id":2,"b":2}
+    "#};
+
+    let rows = rows(
+        ndjson1.as_bytes(),
+        "id",
+        ndjson2.as_bytes(),
+        "id",
+        Opts {
+            sort: true,
+            memory_limit: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // The positions are of the lines before sorting
+    let positions: Vec<_> = rows
+        .map(|row| {
+            let row = row.unwrap();
+            (row.key, row.left_pos, row.right_pos)
+        })
+        .collect();
+
+    assert_eq!(
+        vec![
+            (json!(1), pos(2, 15), pos(1, 0)),
+            (json!(2), pos(1, 0), pos(2, 15)),
+        ],
+        positions
+    );
+}
+
+#[test]
+fn test_rows_stop_early() {
+    let ndjson1 = indoc! {r#"
+        {"id":1,"a":1}
+        {"id":2,"a":2}
+        broken
+    "#};
+
+    let ndjson2 = indoc! {r#"
+        {"id":1,"b":1}
+        {"id":2,"b":2}
+    "#};
+
+    let mut rows = rows(
+        ndjson1.as_bytes(),
+        "id",
+        ndjson2.as_bytes(),
+        "id",
+        Opts::default(),
+    )
+    .unwrap();
+
+    // The broken line is read only after the second row is consumed
+    assert_eq!(json!(1), rows.next().unwrap().unwrap().key);
+    assert_eq!(1, rows.stats().output_rows);
+
+    assert_eq!(json!(2), rows.next().unwrap().unwrap().key);
+    assert_eq!(
+        "FILE1:3:30: Failed to parse JSON: expected value at line 1 column 1: broken",
+        rows.next().unwrap().unwrap_err().to_string()
+    );
+    assert!(rows.next().is_none());
+}
+
+// Source of the records in memory (`None` is a malformed record)
+struct VecSource(Vec<Option<Value>>, u64);
+
+impl RecordSource for VecSource {
+    fn read(&mut self) -> anyhow::Result<Option<Record>> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+
+        self.1 += 1;
+
+        match self.0.remove(0) {
+            Some(json) => Ok(Some(Record {
+                raw: json.to_string(),
+                json: Some(json),
+                line: self.1,
+                offset: 0,
+            })),
+            None => Err(MalformedRecord {
+                raw: "?".to_string(),
+                line: self.1,
+                offset: 0,
+                error: anyhow::anyhow!("broken"),
+            }
+            .into()),
+        }
+    }
+}
+
+// Sink collecting the key and the line numbers of each row
+#[derive(Default)]
+struct VecSink(Vec<(Value, Option<u64>, Option<u64>)>, bool);
+
+impl RecordSink for VecSink {
+    fn write(
+        &mut self,
+        key: &Value,
+        left: Option<&Record>,
+        right: Option<&Record>,
+    ) -> anyhow::Result<()> {
+        self.0
+            .push((key.clone(), left.map(|r| r.line), right.map(|r| r.line)));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.1 = true;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_join_record_source_and_sink() {
+    let source1 = VecSource(
+        vec![
+            Some(json!({"id":1,"a":1})),
+            None,
+            Some(json!({"id":2,"a":2})),
+        ],
+        0,
+    );
+    let source2 = VecSource(vec![Some(json!({"id":2,"b":1}))], 0);
+    let mut sink = VecSink::default();
+
+    let stats = join_into(
+        Box::new(source1),
+        "id",
+        Box::new(source2),
+        "id",
+        &mut sink,
+        Opts {
+            outer: Some(Outer::Left),
+            on_error: OnError::Skip,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec![(json!(1), Some(1), None), (json!(2), Some(3), Some(1))],
+        sink.0
+    );
+    assert!(sink.1);
+    assert_eq!(3, stats.file1.lines);
+    assert_eq!(1, stats.malformed_lines);
+}
+
+#[test]
+fn test_join_record_source_malformed() {
+    let source1 = VecSource(vec![Some(json!({"id":1})), None], 0);
+    let source2 = VecSource(vec![Some(json!({"id":1}))], 0);
+    let mut sink = VecSink::default();
+
+    let r = join_into(
+        Box::new(source1),
+        "id",
+        Box::new(source2),
+        "id",
+        &mut sink,
+        Opts::default(),
+    );
+
+    assert_eq!("FILE1:2:0: broken", r.unwrap_err().to_string());
+}
+
+#[test]
+fn test_block_replay() {
+    let mut block = Block::new(json!(1), 40, true);
+
+    for i in 1..=3 {
+        let json = json!({"id": 1, "n": i});
+
+        block
+            .push(Keyed {
+                record: Record {
+                    raw: format!("{}\n", json),
+                    json: Some(json),
+                    line: i,
+                    offset: 0,
+                },
+                key: json!(1),
+            })
+            .unwrap();
+    }
+
+    let replay = |block: &mut Block| {
+        let mut cursor = block.cursor().unwrap();
+        let mut recs = vec![];
+
+        while let Some(rec) = block.next(&mut cursor).unwrap() {
+            recs.push(rec);
+        }
+
+        recs
+    };
+
+    let recs1 = replay(&mut block);
+    let recs2 = replay(&mut block);
+
+    // The first two lines are kept in memory and the last one is spilled
+    let lines: Vec<_> = recs2.iter().map(|rec| rec.line).collect();
+    assert_eq!(vec![1, 2, 3], lines);
+    assert!(Rc::ptr_eq(&recs1[0], &recs2[0]));
+    assert!(Rc::ptr_eq(&recs1[1], &recs2[1]));
+    assert!(!Rc::ptr_eq(&recs1[2], &recs2[2]));
+    assert_eq!(Some(json!({"id": 1, "n": 3})), recs2[2].json);
+}